@@ -0,0 +1,57 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// First word of the lines of an authenticated revocation file that carry a MAC.
+pub(crate) const MAC_PREFIX: &str = "mac";
+
+/// Key for authenticating the revocation file, derived with a [`LabelKey`](crate::LabelKey).
+#[derive(Clone)]
+pub struct AuthenticationKey(pub(crate) [u8; 32]);
+
+impl fmt::Debug for AuthenticationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthenticationKey(..)")
+    }
+}
+
+/// Chain of HMAC-SHA256 tags over the lines of an authenticated revocation file.
+///
+/// Each tag covers the previous tag and the next line, so a tag authenticates
+/// all lines before it including their order.
+pub(crate) struct MacChain {
+    key: AuthenticationKey,
+    tag: [u8; 32],
+}
+
+impl MacChain {
+    /// Start the chain with the header line of the file.
+    pub(crate) fn new(key: &AuthenticationKey, header: &str) -> Self {
+        let mut chain = Self {
+            key: key.clone(),
+            tag: [0; 32],
+        };
+        chain.update(header);
+        chain
+    }
+
+    /// Add a line to the chain.
+    pub(crate) fn update(&mut self, line: &str) {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key.0).expect("HMAC takes keys of any length");
+        mac.update(&self.tag);
+        mac.update(line.as_bytes());
+        self.tag = mac.finalize().into_bytes().into();
+    }
+
+    /// The current tag, encoded as z85.
+    pub(crate) fn tag(&self) -> String {
+        z85::encode(self.tag)
+    }
+
+    /// The line carrying the current tag.
+    pub(crate) fn line(&self) -> String {
+        format!("{} {}", MAC_PREFIX, self.tag())
+    }
+}
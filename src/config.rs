@@ -0,0 +1,398 @@
+use crate::{canonical_domain, Capitalization, PolicyError, Settings, Wordlist, WordlistError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue};
+
+/// Path of the configuration file inside the configuration directory.
+pub const CONFIG_FILENAME: &str = "xkcdget/sites.toml";
+
+/// An error in the configuration file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Line the error occurred in, starting at 1, if it belongs to a line
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings for a domain. Unset values are taken from the profile below.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of words
+    pub words: Option<u8>,
+    /// Name of a compiled-in word list
+    pub list: Option<String>,
+    /// Path of a word list file, takes precedence over `list`
+    pub wordlist: Option<PathBuf>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub symbols: Option<String>,
+    pub digits: Option<usize>,
+    pub separator: Option<String>,
+    pub case: Option<Capitalization>,
+    /// User name of the account
+    pub user: Option<String>,
+    /// Domain whose password is used instead
+    pub alias: Option<String>,
+    /// Free text, shown when the password is generated
+    pub notes: Option<String>,
+}
+
+macro_rules! overlay_fields {
+    ($self:ident, $other:ident, $($field:ident),*) => {
+        $(
+            if $other.$field.is_some() {
+                $self.$field = $other.$field.clone();
+            }
+        )*
+    };
+}
+
+impl Profile {
+    /// Override values of this profile with the values set in `other`.
+    pub fn overlay(&mut self, other: &Profile) {
+        overlay_fields!(
+            self, other, words, list, wordlist, min_length, max_length, symbols, digits, separator,
+            case, user, alias, notes
+        );
+    }
+
+    /// Build derivation settings from this profile.
+    pub fn settings(&self) -> Result<Settings, ProfileError> {
+        let mut settings = Settings::default();
+        if let Some(words) = self.words {
+            settings.amount_words = words;
+        }
+        settings.user = self.user.clone().filter(|user| !user.is_empty());
+        if let Some(path) = &self.wordlist {
+            settings.wordlist = Wordlist::load(path).map_err(ProfileError::Wordlist)?;
+        } else if let Some(name) = &self.list {
+            settings.wordlist =
+                Wordlist::by_name(name).ok_or_else(|| ProfileError::UnknownList(name.clone()))?;
+        }
+        let policy = &mut settings.policy;
+        policy.min_length = self.min_length.unwrap_or(policy.min_length);
+        policy.max_length = self.max_length.or(policy.max_length);
+        policy.symbols = self.symbols.clone().unwrap_or(policy.symbols.clone());
+        policy.digits = self.digits.unwrap_or(policy.digits);
+        policy.separator = self.separator.clone().unwrap_or(policy.separator.clone());
+        policy.capitalization = self.case.unwrap_or(policy.capitalization);
+        policy.validate().map_err(ProfileError::Policy)?;
+        Ok(settings)
+    }
+}
+
+/// Errors that can occur when building settings from a profile.
+#[derive(Debug)]
+pub enum ProfileError {
+    Wordlist(WordlistError),
+    UnknownList(String),
+    Policy(PolicyError),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wordlist(e) => write!(f, "invalid word list: {}", e),
+            Self::UnknownList(name) => {
+                let names: Vec<&str> = Wordlist::builtin_names().collect();
+                write!(
+                    f,
+                    "unknown word list {}, known lists: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+            Self::Policy(e) => write!(f, "invalid policy: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// Per-domain configuration.
+///
+/// The file is TOML: a `[default]` table and one `[sites."example.com"]`
+/// table per domain, holding string and integer values.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Profile for all domains
+    pub default: Profile,
+    /// Profiles of single domains
+    pub sites: BTreeMap<String, Profile>,
+    /// Line of the default table header, for error messages
+    default_line: Option<usize>,
+    /// Line of each site table header, for error messages
+    lines: BTreeMap<String, usize>,
+}
+
+impl Config {
+    /// Default location of the configuration file, following the XDG base directory spec.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join(CONFIG_FILENAME))
+    }
+
+    /// Load the configuration file. A missing file is an empty configuration.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Vec<ConfigError>> {
+        match read_to_string(&path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(vec![ConfigError {
+                line: None,
+                message: format!("cannot read {}: {}", path.as_ref().display(), e),
+            }]),
+        }
+    }
+
+    /// Parse the configuration, collecting all errors.
+    pub fn parse(content: &str) -> Result<Self, Vec<ConfigError>> {
+        // line of a position in the file, starting at 1
+        let line_of = |span: Range<usize>| content[..span.start].matches('\n').count() + 1;
+        let (document, syntax_errors) = DeTable::parse_recoverable(content);
+        if !syntax_errors.is_empty() {
+            return Err(syntax_errors
+                .iter()
+                .map(|e| ConfigError {
+                    line: e.span().map(line_of),
+                    message: e.message().to_string(),
+                })
+                .collect());
+        }
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+
+        for (name, value) in document.get_ref() {
+            let line = line_of(name.span());
+            match (name.get_ref().as_ref(), value.get_ref()) {
+                ("default", DeValue::Table(table)) => {
+                    config.default_line = Some(line);
+                    set_values(&mut config.default, table, &line_of, &mut errors);
+                }
+                ("sites", DeValue::Table(sites)) => {
+                    for (domain, site) in sites {
+                        let line = line_of(domain.span());
+                        let domain = domain.get_ref().to_string();
+                        let DeValue::Table(table) = site.get_ref() else {
+                            errors.push(ConfigError {
+                                line: Some(line),
+                                message: format!("sites.\"{}\" must be a table", domain),
+                            });
+                            continue;
+                        };
+                        let mut profile = Profile::default();
+                        set_values(&mut profile, table, &line_of, &mut errors);
+                        config.lines.insert(domain.clone(), line);
+                        config.sites.insert(domain, profile);
+                    }
+                }
+                (name, _) => errors.push(ConfigError {
+                    line: Some(line),
+                    message: format!(
+                        "unknown table {}, expected default or sites.\"DOMAIN\"",
+                        name
+                    ),
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            errors.sort_by_key(|e| e.line);
+            Err(errors)
+        }
+    }
+
+    /// The configuration with table names and alias targets reduced to canonical domains.
+    ///
+    /// Tables whose names have the same canonical domain are combined, the
+    /// table whose name is canonical taking precedence.
+    pub fn canonicalize(&self) -> Self {
+        let mut config = Self {
+            default: self.default.clone(),
+            default_line: self.default_line,
+            ..Self::default()
+        };
+        // canonical names last, so that their tables take precedence
+        let mut names: Vec<&String> = self.sites.keys().collect();
+        names.sort_by_key(|name| canonical_domain(name) == **name);
+        for name in names {
+            let mut profile = self.sites[name].clone();
+            profile.alias = profile.alias.map(|alias| canonical_domain(&alias));
+            let domain = canonical_domain(name);
+            config
+                .sites
+                .entry(domain.clone())
+                .or_default()
+                .overlay(&profile);
+            if let Some(&line) = self.lines.get(name) {
+                config.lines.insert(domain, line);
+            }
+        }
+        config
+    }
+
+    /// Validate all profiles and aliases, collecting all errors.
+    ///
+    /// Table names and alias targets that aren't canonical domains are reported
+    /// too, as domains are looked up by their canonical domain.
+    pub fn check(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let line = |name: &str| self.lines.get(name).copied();
+        let not_canonical = |what: String, name: &str| {
+            let canonical = canonical_domain(name);
+            if canonical.is_empty() {
+                Some(format!("{} {} isn't a domain", what, name))
+            } else if canonical != name {
+                Some(format!(
+                    "{} {} isn't a canonical domain, use {}",
+                    what, name, canonical
+                ))
+            } else {
+                None
+            }
+        };
+        for (domain, profile) in &self.sites {
+            let alias = profile
+                .alias
+                .as_deref()
+                .and_then(|alias| not_canonical(format!("{}: alias", domain), alias));
+            for message in [not_canonical("table".to_string(), domain), alias]
+                .into_iter()
+                .flatten()
+            {
+                errors.push(ConfigError {
+                    line: line(domain),
+                    message,
+                });
+            }
+        }
+        if let Err(e) = self.default.settings() {
+            errors.push(ConfigError {
+                line: self.default_line,
+                message: e.to_string(),
+            });
+        }
+        for domain in self.sites.keys() {
+            let result = self.resolve(domain).and_then(|(_, profile)| {
+                profile.settings().map_err(|e| ConfigError {
+                    line: line(domain),
+                    message: format!("{}: {}", domain, e),
+                })
+            });
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+        errors.sort_by_key(|e| e.line);
+        errors
+    }
+
+    /// Follow aliases and return the domain to derive the password for and its profile.
+    ///
+    /// The profile is the default profile, overlaid with the profiles along the
+    /// alias chain, the domain that was asked for taking precedence.
+    pub fn resolve(&self, domain: &str) -> Result<(String, Profile), ConfigError> {
+        let mut chain = vec![domain.to_string()];
+        while let Some(target) = self
+            .sites
+            .get(chain.last().expect("Chain is not empty"))
+            .and_then(|p| p.alias.clone())
+        {
+            if chain.contains(&target) {
+                return Err(ConfigError {
+                    line: self.lines.get(domain).copied(),
+                    message: format!("alias cycle: {} -> {}", chain.join(" -> "), target),
+                });
+            }
+            chain.push(target);
+        }
+
+        let mut profile = self.default.clone();
+        for name in chain.iter().rev() {
+            if let Some(site) = self.sites.get(name) {
+                profile.overlay(site);
+            }
+        }
+        profile.alias = None;
+        let target = chain.pop().expect("Chain is not empty");
+        Ok((target, profile))
+    }
+}
+
+/// Parse a string value.
+fn parse_string(value: &DeValue) -> Result<String, String> {
+    match value {
+        DeValue::String(s) => Ok(s.to_string()),
+        _ => Err(format!("expected a string, got {}", value.type_str())),
+    }
+}
+
+/// Parse a non-negative integer value.
+fn parse_integer<T: TryFrom<u64>>(value: &DeValue) -> Result<T, String> {
+    let DeValue::Integer(integer) = value else {
+        return Err(format!("expected an integer, got {}", value.type_str()));
+    };
+    u64::from_str_radix(integer.as_str(), integer.radix())
+        .ok()
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| format!("expected a non-negative integer, got {}", integer))
+}
+
+/// Set the keys of a table in a profile, collecting the errors.
+fn set_values(
+    profile: &mut Profile,
+    table: &DeTable,
+    line_of: &impl Fn(Range<usize>) -> usize,
+    errors: &mut Vec<ConfigError>,
+) {
+    for (key, value) in table {
+        if let Err(message) = set_value(profile, key.get_ref(), value.get_ref()) {
+            errors.push(ConfigError {
+                line: Some(line_of(key.span())),
+                message,
+            });
+        }
+    }
+}
+
+/// Set a key of a profile.
+fn set_value(profile: &mut Profile, key: &str, value: &DeValue) -> Result<(), String> {
+    match key {
+        "words" => match parse_integer(value)? {
+            0 => return Err("words must be at least 1".to_string()),
+            words => profile.words = Some(words),
+        },
+        "list" => profile.list = Some(parse_string(value)?),
+        "wordlist" => profile.wordlist = Some(PathBuf::from(parse_string(value)?)),
+        "min_length" => profile.min_length = Some(parse_integer(value)?),
+        "max_length" => profile.max_length = Some(parse_integer(value)?),
+        "symbols" => profile.symbols = Some(parse_string(value)?),
+        "digits" => profile.digits = Some(parse_integer(value)?),
+        "separator" => profile.separator = Some(parse_string(value)?),
+        "case" => profile.case = Some(parse_string(value)?.parse()?),
+        "user" => profile.user = Some(parse_string(value)?),
+        "alias" => profile.alias = Some(parse_string(value)?),
+        "notes" => profile.notes = Some(parse_string(value)?),
+        _ => return Err(format!("unknown key {}", key)),
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Public suffix list from https://publicsuffix.org/list/
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+/// Reduce user input like `https://www.GitHub.com:443/login` to a canonical domain like `github.com`.
+///
+/// The input is lower-cased, scheme, user info, port, path and trailing dots
+/// are stripped, internationalized labels are converted to punycode and the
+/// domain is reduced to the registrable domain according to the public
+/// suffix list. Input that is not a domain, like an IP address or a single
+/// word, is only lower-cased.
+pub fn canonical_domain(input: &str) -> String {
+    let mut host = input.trim().to_lowercase();
+
+    // strip scheme, path, query, fragment and user info
+    if let Some((_, rest)) = host.split_once("://") {
+        host = rest.to_string();
+    }
+    if let Some(end) = host.find(['/', '?', '#']) {
+        host.truncate(end);
+    }
+    if let Some((_, rest)) = host.rsplit_once('@') {
+        host = rest.to_string();
+    }
+
+    // IPv6 addresses are kept as they are, without port
+    if host.starts_with('[') {
+        if let Some(end) = host.find(']') {
+            host.truncate(end + 1);
+        }
+        return host;
+    }
+
+    // IPv6 addresses without brackets have several colons and no port
+    if host.matches(':').count() > 1 {
+        return host;
+    }
+
+    // strip port and trailing dots
+    if let Some((name, port)) = host.rsplit_once(':') {
+        if port.chars().all(|c| c.is_ascii_digit()) {
+            host = name.to_string();
+        }
+    }
+    let host = host.trim_end_matches('.');
+
+    // IPv4 addresses are kept as they are
+    if host.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return host.to_string();
+    }
+
+    let host: Vec<String> = host.split('.').map(label_to_ascii).collect();
+    let host = host.join(".");
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    registrable_domain(host).to_string()
+}
+
+/// Reduce a domain to the public suffix plus one label, e.g. `www.example.co.uk` to `example.co.uk`.
+///
+/// Domains that are a public suffix themselves are returned unchanged.
+pub fn registrable_domain(domain: &str) -> &str {
+    let rules = public_suffix_rules();
+    let labels: Vec<&str> = domain.split('.').collect();
+
+    // the first matching suffix is the longest one
+    let mut suffix_len = 1; // implicit rule "*"
+    for i in 0..labels.len() {
+        let candidate = labels[i..].join(".");
+        let parent = labels[(i + 1)..].join(".");
+        if rules.contains(&format!("!{}", candidate)) {
+            suffix_len = labels.len() - i - 1;
+            break;
+        }
+        if rules.contains(&candidate)
+            || (i + 1 < labels.len() && rules.contains(&format!("*.{}", parent)))
+        {
+            suffix_len = labels.len() - i;
+            break;
+        }
+    }
+
+    if suffix_len >= labels.len() {
+        return domain;
+    }
+    let skip: usize = labels[..(labels.len() - suffix_len - 1)]
+        .iter()
+        .map(|l| l.len() + 1)
+        .sum();
+    &domain[skip..]
+}
+
+/// Rules of the public suffix list, with internationalized labels in punycode.
+///
+/// The list is only parsed once, as many domains may be normalized in one run.
+fn public_suffix_rules() -> &'static HashSet<String> {
+    static RULES: OnceLock<HashSet<String>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with("//"))
+            .map(|rule| {
+                let labels: Vec<String> = rule.split('.').map(label_to_ascii).collect();
+                labels.join(".")
+            })
+            .collect()
+    })
+}
+
+/// Convert a label to punycode if it is not ASCII.
+fn label_to_ascii(label: &str) -> String {
+    if label.is_ascii() {
+        label.to_string()
+    } else {
+        format!("xn--{}", punycode_encode(label))
+    }
+}
+
+const BASE: u32 = 36;
+const TMIN: u32 = 1;
+const TMAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// Encode a string with punycode as specified in RFC 3492.
+fn punycode_encode(input: &str) -> String {
+    let input: Vec<u32> = input.chars().map(u32::from).collect();
+    let mut output: String = input
+        .iter()
+        .filter(|&&c| c < 0x80)
+        .map(|&c| char::from_u32(c).expect("ASCII is valid"))
+        .collect();
+    let basic = output.len() as u32;
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let (mut n, mut delta, mut bias) = (INITIAL_N, 0, INITIAL_BIAS);
+    let mut handled = basic;
+    while (handled as usize) < input.len() {
+        let m = *input
+            .iter()
+            .filter(|&&c| c >= n)
+            .min()
+            .expect("Unhandled code points left");
+        delta += (m - n) * (handled + 1);
+        n = m;
+        for &c in &input {
+            if c < n {
+                delta += 1;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = k.saturating_sub(bias).clamp(TMIN, TMAX);
+                    if q < t {
+                        break;
+                    }
+                    output.push(punycode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(punycode_digit(q));
+                bias = punycode_adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta += 1;
+        n += 1;
+    }
+    output
+}
+
+/// Bias adaptation function of RFC 3492.
+fn punycode_adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (((BASE - TMIN + 1) * delta) / (delta + SKEW))
+}
+
+/// Encode a punycode digit, 0..=25 as a..=z and 26..=35 as 0..=9.
+fn punycode_digit(d: u32) -> char {
+    let d = d as u8;
+    if d < 26 {
+        (b'a' + d) as char
+    } else {
+        (b'0' + d - 26) as char
+    }
+}
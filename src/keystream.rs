@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::KEY_LEN;
+
+/// Error for a key that isn't a z85 encoded key of [`KEY_LEN`] bytes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyError {
+    /// The key isn't valid z85
+    Encoding,
+    /// The decoded key has the wrong number of bytes
+    Length(usize),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Encoding => write!(f, "key isn't z85 encoded"),
+            Self::Length(len) => write!(f, "key has {} bytes, expected {}", len, KEY_LEN),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// Deterministic, unbounded stream of 64-bit blocks derived from a key.
+///
+/// The first blocks are the key itself, read as little-endian u64, so passwords
+/// of up to four words are unchanged. Further blocks are taken from
+/// `sha256(key || counter)` with counter starting at 1.
+pub struct KeyStream {
+    key: Vec<u8>,
+    chunk: [u8; KEY_LEN],
+    block: usize,
+}
+
+const BLOCKS_PER_CHUNK: usize = KEY_LEN / 8;
+
+impl KeyStream {
+    /// Create a stream from a z85 encoded key.
+    pub fn new(key: &str) -> Result<Self, KeyError> {
+        let key = z85::decode(key).map_err(|_| KeyError::Encoding)?;
+        let chunk = key
+            .as_slice()
+            .try_into()
+            .map_err(|_| KeyError::Length(key.len()))?;
+        Ok(Self {
+            key,
+            chunk,
+            block: 0,
+        })
+    }
+
+    /// Return the next 64 bits of the stream.
+    pub fn next_u64(&mut self) -> u64 {
+        let offset = 8 * (self.block % BLOCKS_PER_CHUNK);
+        if offset == 0 && self.block > 0 {
+            // extend the stream with the next chunk
+            let counter = (self.block / BLOCKS_PER_CHUNK) as u64;
+            let mut input = self.key.clone();
+            input.extend_from_slice(&counter.to_le_bytes());
+            let digest = hex::decode(sha256::digest(input)).expect("Cannot hex-decode digest");
+            self.chunk = digest.try_into().expect("sha256 digest has KEY_LEN bytes");
+        }
+        self.block += 1;
+        u64::from_le_bytes(
+            self.chunk[offset..(offset + 8)]
+                .try_into()
+                .expect("Cannot convert slice to u64"),
+        )
+    }
+
+    /// Return a uniformly distributed index below `len`.
+    ///
+    /// Uses rejection sampling: blocks from the top of the u64 range that would
+    /// make some indices more likely than others are skipped. For lengths that
+    /// are powers of two nothing is ever rejected, so the result is simply the
+    /// block modulo `len`.
+    pub fn next_index(&mut self, len: usize) -> usize {
+        assert!(len > 0, "Cannot choose from an empty range");
+        let len = len as u64;
+        // 2^64 mod len, the size of the biased zone at the top of the range
+        let biased = (u64::MAX % len + 1) % len;
+        loop {
+            let block = self.next_u64();
+            if block <= u64::MAX - biased {
+                return (block % len) as usize;
+            }
+        }
+    }
+}
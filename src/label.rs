@@ -0,0 +1,96 @@
+use hmac::{Hmac, Mac};
+use salsa20::cipher::{KeyIvInit, StreamCipher};
+use salsa20::XSalsa20;
+use scrypt::{scrypt, Params};
+use sha2::Sha256;
+
+use crate::AuthenticationKey;
+
+/// Salt for deriving the label key from the master password.
+const LABEL_KEY_SALT: &str = "xkcdget-revocation-label";
+const NONCE_LEN: usize = 24;
+/// Input for deriving the authentication key from the MAC key.
+const AUTHENTICATION_KEY_INFO: &str = "xkcdget-revocation-authentication";
+
+/// Key for encrypting the labels of revocation entries, derived from the master password.
+///
+/// Labels are encrypted with XSalsa20. The nonce is an HMAC-SHA256 over the
+/// revocation hash and the label, so it doubles as authentication tag and a
+/// label cannot be moved to another entry.
+pub struct LabelKey {
+    encryption_key: [u8; 32],
+    mac_key: [u8; 32],
+}
+
+impl LabelKey {
+    /// Derive the label key from the master password.
+    pub fn derive(master_password: &str) -> Self {
+        let mut key = [0; 64];
+        let (log_n, r, p) = (16, 8, 16);
+        let scrypt_params =
+            Params::new(log_n, r, p, key.len()).expect("Cannot create scrypt parameters");
+        scrypt(
+            master_password.as_bytes(),
+            LABEL_KEY_SALT.as_bytes(),
+            &scrypt_params,
+            &mut key,
+        )
+        .expect("Output length of scrypt is valid");
+        let (encryption_key, mac_key) = key.split_at(32);
+        Self {
+            encryption_key: encryption_key.try_into().expect("Key halves have 32 bytes"),
+            mac_key: mac_key.try_into().expect("Key halves have 32 bytes"),
+        }
+    }
+
+    /// Derive the key for authenticating the revocation file, independent of the label keys.
+    pub fn authentication_key(&self) -> AuthenticationKey {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.mac_key).expect("HMAC takes keys of any length");
+        mac.update(AUTHENTICATION_KEY_INFO.as_bytes());
+        AuthenticationKey(mac.finalize().into_bytes().into())
+    }
+
+    /// Calculate the nonce of a label belonging to a revocation hash.
+    fn nonce(&self, hash: &str, label: &[u8]) -> [u8; NONCE_LEN] {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.mac_key).expect("HMAC takes keys of any length");
+        mac.update(hash.as_bytes());
+        mac.update(&[0]);
+        mac.update(label);
+        let tag = mac.finalize().into_bytes();
+        tag[..NONCE_LEN]
+            .try_into()
+            .expect("HMAC-SHA256 is longer than the nonce")
+    }
+
+    /// Apply the key stream for the given nonce.
+    fn apply(&self, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+        let mut cipher = XSalsa20::new(&self.encryption_key.into(), nonce.into());
+        cipher.apply_keystream(data);
+    }
+
+    /// Encrypt the label of a revocation hash and encode it as z85.
+    pub fn encrypt(&self, hash: &str, label: &str) -> String {
+        let nonce = self.nonce(hash, label.as_bytes());
+        let mut data = label.as_bytes().to_vec();
+        self.apply(&nonce, &mut data);
+        z85::encode([nonce.as_slice(), &data].concat())
+    }
+
+    /// Decrypt a label, returning None if it doesn't belong to the hash or the key is wrong.
+    pub fn decrypt(&self, hash: &str, encrypted: &str) -> Option<String> {
+        let data = z85::decode(encrypted).ok()?;
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().ok()?;
+        let mut label = data.to_vec();
+        self.apply(&nonce, &mut label);
+        if self.nonce(hash, &label) != nonce {
+            return None;
+        }
+        String::from_utf8(label).ok()
+    }
+}
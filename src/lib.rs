@@ -0,0 +1,400 @@
+//! Deterministic password generator, implementing the xkcd scheme.
+//!
+//! The `xkcdget` binary is only a frontend for this library. Reading the
+//! master password, the domain and the revocation list is up to the caller.
+
+use scrypt::{scrypt, Params};
+use std::fmt;
+
+mod authentication;
+mod config;
+mod domain;
+mod keystream;
+mod label;
+mod policy;
+mod revocation;
+mod state;
+mod transfer;
+mod wordlist;
+
+pub use authentication::AuthenticationKey;
+pub use config::{Config, ConfigError, Profile, ProfileError, CONFIG_FILENAME};
+pub use domain::{canonical_domain, registrable_domain};
+pub use keystream::{KeyError, KeyStream};
+pub use label::LabelKey;
+pub use policy::{Capitalization, Policy, PolicyError};
+pub use revocation::{
+    decode_revocation_hash, diff_revocation_lists, format_revocation_list, merge_revocation_lists,
+    parse_revocation_list, read_revocation_list, revocation_hash, FileRevocationStore,
+    LayeredRevocationStore, MemoryRevocationStore, Reason, RevocationEntry, RevocationSet,
+    RevocationStore, AUTHENTICATED_FORMAT_VERSION, REVOCATION_AUTHENTICATION_ENV,
+    REVOCATION_DATA_FILENAME, REVOCATION_FILE_ENV, REVOCATION_FORMAT_VERSION, REVOCATION_HASH_LEN,
+    REVOCATION_LIST_FILENAME, REVOCATION_SOURCES_ENV,
+};
+pub use state::{RevocationState, StateFile, STATE_FILENAME};
+pub use transfer::{
+    export_revocations, import_revocations, TransferError, TRANSFER_CHUNK_HASHES, TRANSFER_PREFIX,
+};
+pub use wordlist::{Wordlist, WordlistError, DEFAULT_WORDLIST_NAME, WORDLIST};
+
+pub const WORDLIST_LEN: usize = 2048;
+pub const KEY_LEN: usize = 32;
+pub const DEFAULT_AMOUNT_WORDS: u8 = 4;
+
+/// Settings that influence how a password is derived.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Number of words in the password
+    pub amount_words: u8,
+    /// Words to choose from
+    pub wordlist: Wordlist,
+    /// Rules the password has to follow
+    pub policy: Policy,
+    /// User name, for several accounts on the same domain
+    pub user: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            amount_words: DEFAULT_AMOUNT_WORDS,
+            wordlist: Wordlist::builtin(),
+            policy: Policy::default(),
+            user: None,
+        }
+    }
+}
+
+/// A generated password.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Password {
+    password: String,
+    /// Number of candidate words each word has been chosen from
+    candidates: Vec<usize>,
+}
+
+impl Password {
+    /// The password as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.password
+    }
+
+    /// Number of words of the password.
+    pub fn words(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Number of candidate words each word has been chosen from.
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+
+    /// Entropy of the chosen words in bits.
+    pub fn entropy_bits(&self) -> f32 {
+        self.candidates
+            .iter()
+            .map(|&candidates| (candidates as f32).log2())
+            .sum()
+    }
+}
+
+impl fmt::Display for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.password)
+    }
+}
+
+/// The first non-revoked key of a domain.
+#[derive(Clone, Debug)]
+pub struct DerivedKey {
+    /// z85 encoded scrypt output
+    pub key: String,
+    /// Iteration that was used for the salt
+    pub iteration: u64,
+    /// Revocation hashes of the iterations that have been skipped
+    pub skipped: Vec<String>,
+}
+
+/// Errors that can occur when choosing the words of a password.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordError {
+    /// The key isn't a z85 encoded key of [`KEY_LEN`] bytes
+    Key(KeyError),
+    /// The policy can't be satisfied
+    Policy(PolicyError),
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(e) => write!(f, "invalid key: {}", e),
+            Self::Policy(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+impl From<KeyError> for PasswordError {
+    fn from(e: KeyError) -> Self {
+        Self::Key(e)
+    }
+}
+
+impl From<PolicyError> for PasswordError {
+    fn from(e: PolicyError) -> Self {
+        Self::Policy(e)
+    }
+}
+
+/// Password entropy in bits per word and in total, unless a maximum length reduces it.
+///
+/// The entropy of a generated password is given by [`Password::entropy_bits`].
+pub fn entropy_bits(settings: &Settings) -> (f32, f32) {
+    let bits_per_word = settings.wordlist.bits_per_word();
+    (bits_per_word, bits_per_word * settings.amount_words as f32)
+}
+
+/// Build the salt for a domain, an optional user name and an iteration.
+///
+/// Without a user name the original format `{iteration}:{domain}` is used, so
+/// existing passwords stay the same. With a user name the versioned format
+/// `xkcdget2:{iteration}:{domain length}:{domain}:{user}` is used, where the
+/// length keeps domain and user name apart even if they contain colons.
+pub fn salt(domain: &str, user: Option<&str>, iteration: u64) -> String {
+    match user {
+        None => format!("{}:{}", iteration, domain),
+        Some(user) => format!(
+            "xkcdget2:{}:{}:{}:{}",
+            iteration,
+            domain.len(),
+            domain,
+            user
+        ),
+    }
+}
+
+/// Version of the salt format of [`salt`]: 1 without a user name, 2 with one.
+pub fn salt_version(user: Option<&str>) -> u32 {
+    match user {
+        None => 1,
+        Some(_) => 2,
+    }
+}
+
+/// Calculate the salted hash of the master password and the account for one iteration.
+pub fn derive_key_iteration(
+    master_password: &str,
+    domain: &str,
+    user: Option<&str>,
+    iteration: u64,
+) -> String {
+    let mut key = [0; KEY_LEN];
+    let (log_n, r, p) = (16, 8, 16);
+    let scrypt_params = Params::new(log_n, r, p, KEY_LEN).expect("Cannot create scrypt parameters");
+    let salt = salt(domain, user, iteration);
+    scrypt(
+        master_password.as_bytes(),
+        salt.as_bytes(),
+        &scrypt_params,
+        &mut key,
+    )
+    .expect("Output length of scrypt is valid");
+    z85::encode(key)
+}
+
+/// A key of a domain for one iteration.
+#[derive(Clone, Debug)]
+pub struct KeyIteration {
+    pub iteration: u64,
+    /// z85 encoded scrypt output
+    pub key: String,
+    /// Hash used for revocation
+    pub hash: String,
+    /// Whether the hash has been revoked
+    pub revoked: bool,
+}
+
+/// Calculate the keys of all iterations of a domain up to the first one that has not been revoked.
+pub fn key_history(
+    master_password: &str,
+    domain: &str,
+    user: Option<&str>,
+    revoked: &RevocationSet,
+) -> Vec<KeyIteration> {
+    let mut history = Vec::new();
+    for iteration in 0.. {
+        let key = derive_key_iteration(master_password, domain, user, iteration);
+
+        // if the key has been revoked do another round, else stop
+        let hash = revocation_hash(&key);
+        let is_revoked = revoked.contains(&hash);
+        history.push(KeyIteration {
+            iteration,
+            key,
+            hash,
+            revoked: is_revoked,
+        });
+        if !is_revoked {
+            return history;
+        }
+    }
+
+    unreachable!("The unconditional loop above must return at the first non-revoked key");
+}
+
+/// Calculate the salted hash of the master password and the domain.
+///
+/// Iterations are hashed until one is found that has not been revoked.
+pub fn derive_key(
+    master_password: &str,
+    domain: &str,
+    user: Option<&str>,
+    revoked: &RevocationSet,
+) -> DerivedKey {
+    let mut history = key_history(master_password, domain, user, revoked);
+    let current = history.pop().expect("History ends with the current key");
+    DerivedKey {
+        key: current.key,
+        iteration: current.iteration,
+        skipped: history.into_iter().map(|k| k.hash).collect(),
+    }
+}
+
+/// Choose a capitalized word of at most `max_chars` characters from the key stream.
+///
+/// Returns the word and the number of candidates it has been chosen from.
+fn choose_word(
+    key_stream: &mut KeyStream,
+    settings: &Settings,
+    max_chars: usize,
+) -> Option<(String, usize)> {
+    let wordlist = &settings.wordlist;
+    let (index, candidates) = if max_chars >= wordlist.max_word_chars() {
+        (key_stream.next_index(wordlist.len()), wordlist.len())
+    } else {
+        // only choose among words that are short enough
+        let candidates: Vec<usize> = (0..wordlist.len())
+            .filter(|&i| wordlist.get(i).chars().count() <= max_chars)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        (
+            candidates[key_stream.next_index(candidates.len())],
+            candidates.len(),
+        )
+    };
+    let word = settings.policy.capitalization.apply(wordlist.get(index));
+    Some((word, candidates))
+}
+
+/// Choose `amount_words` words of at most `max_chars` characters each.
+///
+/// Returns the words, the number of candidates of each word and the rest of the key stream.
+fn choose_words(
+    mut key_stream: KeyStream,
+    settings: &Settings,
+    amount_words: usize,
+    max_chars: usize,
+) -> Option<(Vec<String>, Vec<usize>, KeyStream)> {
+    let (words, candidates) = (0..amount_words)
+        .map(|_| choose_word(&mut key_stream, settings, max_chars))
+        .collect::<Option<Vec<(String, usize)>>>()?
+        .into_iter()
+        .unzip();
+    Some((words, candidates, key_stream))
+}
+
+/// Choose the words of a password from a z85 encoded key and apply the policy.
+///
+/// If the password would exceed the maximum length, all words are chosen again
+/// among the words that are short enough, reducing the number of words if there
+/// are none. Words are added until the minimum length is reached.
+pub fn password_from_key(key: &str, settings: &Settings) -> Result<Password, PasswordError> {
+    let policy = &settings.policy;
+    policy.validate()?;
+    let length = |words: &[String]| policy.assemble(words).chars().count();
+
+    // choose words
+    let amount_words = settings.amount_words as usize;
+    let (mut words, mut candidates, mut key_stream) =
+        choose_words(KeyStream::new(key)?, settings, amount_words, usize::MAX)
+            .expect("Unlimited words can be chosen");
+
+    // keep the maximum length by choosing shorter words, or fewer if that
+    // leaves more entropy
+    if let Some(max_length) = policy.max_length {
+        if length(&words) > max_length {
+            let wordlist = &settings.wordlist;
+            let max_chars = |n: usize| (max_length - policy.overhead(n)) / n;
+            let entropy = |n: usize| {
+                let candidates = (0..wordlist.len())
+                    .filter(|&i| wordlist.get(i).chars().count() <= max_chars(n))
+                    .count();
+                n as f32 * (candidates as f32).log2()
+            };
+            let amount_words = (1..=amount_words)
+                .filter(|&n| policy.overhead(n) < max_length)
+                .map(|n| (n, entropy(n)))
+                .filter(|&(_, bits)| bits > 0.0)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(n, _)| n)
+                .ok_or(PolicyError::Unsatisfiable)?;
+            (words, candidates, key_stream) = choose_words(
+                KeyStream::new(key)?,
+                settings,
+                amount_words,
+                max_chars(amount_words),
+            )
+            .expect("Candidates exist for positive entropy");
+        }
+    }
+
+    // reach the minimum length by adding words
+    while length(&words) < policy.min_length {
+        let max_chars = match policy.max_length {
+            Some(max_length) => {
+                let overhead = policy.overhead(words.len() + 1);
+                let used: usize = words.iter().map(|w| w.chars().count()).sum();
+                max_length
+                    .checked_sub(overhead + used)
+                    .ok_or(PolicyError::Unsatisfiable)?
+            }
+            None => usize::MAX,
+        };
+        let (word, word_candidates) =
+            choose_word(&mut key_stream, settings, max_chars).ok_or(PolicyError::Unsatisfiable)?;
+        words.push(word);
+        candidates.push(word_candidates);
+    }
+
+    // capitalization may change the number of characters, e.g. for "ß"
+    match policy.max_length {
+        Some(max_length) if length(&words) > max_length => Err(PolicyError::Unsatisfiable.into()),
+        _ => Ok(Password {
+            password: policy.assemble(&words),
+            candidates,
+        }),
+    }
+}
+
+/// Derive the password for a domain with default settings, skipping revoked iterations.
+pub fn derive_password(master_password: &str, domain: &str, revoked: &RevocationSet) -> Password {
+    derive_password_with(master_password, domain, revoked, &Settings::default())
+        .expect("The default policy can always be satisfied")
+}
+
+/// Derive the password for a domain, skipping revoked iterations.
+pub fn derive_password_with(
+    master_password: &str,
+    domain: &str,
+    revoked: &RevocationSet,
+    settings: &Settings,
+) -> Result<Password, PasswordError> {
+    let user = settings.user.as_deref();
+    password_from_key(
+        &derive_key(master_password, domain, user, revoked).key,
+        settings,
+    )
+}
@@ -1,211 +1,1676 @@
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
 use rpassword::prompt_password;
-use scrypt::{scrypt, Params};
-use std::env::{args, var};
-use std::fs::{read_to_string, OpenOptions};
-use std::io::{stdin, stdout, BufRead, ErrorKind, IsTerminal, Write};
-
-mod wordlist;
-use wordlist::WORDLIST;
-
-const XKCDGET_VERSION: &str = "2.2.0"; // semantic versioning!
-const WORDLIST_LEN: usize = 2048;
-const KEY_LEN: usize = 32;
-const AMOUNT_WORDS: u8 = 4;
-const REVOCATION_LIST_FILENAME: &str = ".xkcdget-revocation";
-
-/// Return path to revocation file
-fn get_revocation_filename() -> String {
-    let homedir = var("HOME").expect("HOME environment variable unset or invalid");
-    format!("{}/{}", homedir, REVOCATION_LIST_FILENAME)
-}
-
-/// Calculate the hash used for revocation.
-fn get_revocation_hash(password_str: &String) -> String {
-    let hash = hex::decode(sha256::digest(password_str)).expect("Cannot hex-decode passwordStr");
-    z85::encode(hash)
-}
-
-/// calculate and print password entropy
-fn print_entropy() {
-    let bits_per_word = (WORDLIST_LEN as f32).log2();
-    eprintln!(
-        "Entropy: {} bits ({} bits per word)",
-        bits_per_word * AMOUNT_WORDS as f32,
-        bits_per_word
+use std::env::args;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io::{self, stderr, stdin, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use xkcdget::{
+    canonical_domain, derive_key, derive_key_iteration, diff_revocation_lists, entropy_bits,
+    export_revocations, import_revocations, key_history, password_from_key, revocation_hash,
+    salt_version, Config, DerivedKey, FileRevocationStore, LabelKey, LayeredRevocationStore,
+    Password, Profile, Reason, RevocationEntry, RevocationSet, RevocationStore, Settings,
+    StateFile, TransferError, REVOCATION_AUTHENTICATION_ENV,
+};
+
+const XKCDGET_VERSION: &str = "4.0.0"; // semantic versioning!
+
+/// Errors that end xkcdget, each class with its own exit status.
+#[derive(Debug)]
+enum Error {
+    /// Invalid command line
+    Usage(String),
+    /// Domain, master password or revocation string missing or unreadable
+    Input(String),
+    /// Environment variables like `HOME` unset or invalid
+    Environment(String),
+    /// Invalid configuration file or settings
+    Config(String),
+    /// Revocation file unreadable, unwritable, corrupt, not authentic or with lost entries
+    Revocation(String),
+    /// Password policy that can't be satisfied
+    Policy(String),
+    /// Revocation string that can't be exported or imported
+    Transfer(String),
+    /// No revocation that can be undone
+    Unrevoke(String),
+}
+
+impl Error {
+    /// Exit status of the error class, as documented in the README.
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => 2,
+            Self::Input(_) => 3,
+            Self::Environment(_) => 4,
+            Self::Config(_) => 5,
+            Self::Revocation(_) => 6,
+            Self::Policy(_) => 7,
+            Self::Transfer(_) => 8,
+            Self::Unrevoke(_) => 9,
+        }
+    }
+
+    /// Error about the revocation file.
+    fn revocation(context: &str, e: io::Error) -> Self {
+        Self::Revocation(format!("{}: {}", context, e))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(message)
+            | Self::Input(message)
+            | Self::Environment(message)
+            | Self::Config(message)
+            | Self::Revocation(message)
+            | Self::Policy(message)
+            | Self::Transfer(message)
+            | Self::Unrevoke(message) => f.write_str(message),
+        }
+    }
+}
+
+/// How much is reported on stderr besides errors and the requested output.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    /// Only errors
+    Quiet,
+    /// Warnings, what has been changed and how the password has been derived
+    Normal,
+    /// Also the files used and the iteration of the password
+    Verbose,
+}
+
+/// Verbosity of this run, set from the command line.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Whether messages of the level are reported.
+fn reports(level: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Print a message to stderr, unless quiet.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if reports(Verbosity::Normal) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Print a message to stderr if verbose.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if reports(Verbosity::Verbose) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Error for an unset or invalid `HOME`.
+fn home_unset() -> Error {
+    Error::Environment("HOME environment variable unset or invalid".to_string())
+}
+
+/// Return the revocation store: the revocation file and read-only revocation sources
+fn get_revocation_store(
+    file: Option<PathBuf>,
+    sources: Vec<PathBuf>,
+    accept_lost_entries: bool,
+) -> Result<LayeredRevocationStore, Error> {
+    let path = file
+        .or_else(FileRevocationStore::default_path)
+        .ok_or_else(home_unset)?;
+    let mut read_only = LayeredRevocationStore::sources_from_env();
+    read_only.extend(sources.into_iter().map(FileRevocationStore::new));
+    let mut store = LayeredRevocationStore::new(FileRevocationStore::new(path), read_only);
+    let required = std::env::var(REVOCATION_AUTHENTICATION_ENV).is_ok_and(|v| v == "required");
+    let writable = store.writable_mut();
+    writable.set_require_authentication(required);
+    if let Some(state_path) = StateFile::default_path() {
+        writable.set_state_file(StateFile::new(state_path));
+    }
+    writable.set_accept_lost_entries(accept_lost_entries);
+    verbose!("Revocation file: {}", store.writable().path().display());
+    for source in store.read_only() {
+        verbose!("Revocation source: {}", source.path().display());
+    }
+    Ok(store)
+}
+
+/// Whether some revocation files are authenticated, so that the master password is needed to read them
+fn revocation_files_authenticated(
+    store: &LayeredRevocationStore,
+    others: &[FileRevocationStore],
+) -> Result<bool, Error> {
+    let mut needed = store.needs_authentication_key();
+    for other in others {
+        needed = needed.and_then(|n| Ok(n || other.needs_authentication_key()?));
+    }
+    needed.map_err(|e| Error::revocation("Error opening revocation file", e))
+}
+
+/// Use the key derived from the master password to verify authenticated revocation files
+///
+/// The label key is returned, so that it doesn't have to be derived again.
+fn unlock_revocation_files(
+    master_password: &str,
+    store: &mut LayeredRevocationStore,
+    others: &mut [FileRevocationStore],
+) -> LabelKey {
+    let label_key = LabelKey::derive(master_password);
+    let key = label_key.authentication_key();
+    for other in others.iter_mut() {
+        other.set_authentication_key(key.clone());
+    }
+    store.set_authentication_key(key);
+    label_key
+}
+
+/// Unlock the revocation store if it is authenticated, returning the label key if derived
+fn unlock_if_authenticated(
+    master_password: &str,
+    store: &mut LayeredRevocationStore,
+) -> Result<Option<LabelKey>, Error> {
+    Ok(revocation_files_authenticated(store, &[])?
+        .then(|| unlock_revocation_files(master_password, store, &mut [])))
+}
+
+/// print word list
+fn print_wordlist(settings: &Settings) {
+    let wordlist = &settings.wordlist;
+    info!(
+        "Word list: {} ({} words, fingerprint {})",
+        wordlist.name(),
+        wordlist.len(),
+        wordlist.fingerprint()
     );
 }
 
-/// Interactively ask for a domain and return it.
-fn get_domain() -> String {
-    let stdin = stdin();
-    let mut domain = String::new();
+/// print password entropy, warning if the maximum length reduces it
+fn print_entropy(derived: &DerivedPassword, settings: &Settings) {
+    let (bits_per_word, bits) = entropy_bits(settings);
+    match settings.policy.max_length {
+        // allow for rounding when summing up the bits of the words
+        Some(max_length) if derived.entropy_bits + 0.001 < bits => {
+            info!(
+                "Entropy: {:.1} bits ({} words)",
+                derived.entropy_bits, derived.words
+            );
+            info!(
+                "Warning: the maximum length of {} reduces the entropy from {} to {:.1} bits",
+                max_length, bits, derived.entropy_bits
+            );
+        }
+        _ => info!(
+            "Entropy: {} bits ({} bits per word)",
+            derived.entropy_bits, bits_per_word
+        ),
+    }
+}
+
+/// Read a line from stdin, failing at the end of input.
+fn read_stdin_line(what: &str) -> Result<String, Error> {
+    let mut line = String::new();
+    match stdin().lock().read_line(&mut line) {
+        Ok(0) => Err(Error::Input(format!("No {} given", what))),
+        Ok(_) => Ok(line.trim().to_string()),
+        Err(e) => Err(Error::Input(format!("Cannot read {}: {}", what, e))),
+    }
+}
 
-    // Ask for interactive domain input if we're on a terminal
-    if stdin.is_terminal() {
-        print!("Domain: ");
-        stdout().flush().expect("Can't flush stdout");
+/// Interactively ask for a domain and return it.
+fn get_domain() -> Result<String, Error> {
+    // Ask for interactive domain input if we're on a terminal, stdout is only for the password
+    if stdin().is_terminal() {
+        eprint!("Domain: ");
+        stderr()
+            .flush()
+            .map_err(|e| Error::Input(format!("Cannot prompt for domain: {}", e)))?;
     }
 
-    // Read domain, remove newline and return
-    stdin
-        .lock()
-        .read_line(&mut domain)
-        .expect("Expecting argument: Domain");
-    String::from(domain.trim())
+    let domain = read_stdin_line("domain")?;
+    if domain.is_empty() {
+        return Err(Error::Input("No domain given".to_string()));
+    }
+    Ok(domain)
 }
 
 /// Read hashes of passwords that have been revoked.
-fn get_revoked_pw_hashes() -> Vec<String> {
-    // read file
-    let revocation_filename = get_revocation_filename();
-    let file_content = read_to_string(&revocation_filename).unwrap_or_else(|e| {
-        // treat nonexisting file like an empty file
-        if e.kind() == ErrorKind::NotFound {
-            String::new()
-        } else {
-            panic!("Error opening file {}: {}", revocation_filename, e)
+fn get_revoked_pw_hashes(store: &impl RevocationStore) -> Result<RevocationSet, Error> {
+    store
+        .load()
+        .map_err(|e| Error::revocation("Error opening revocation file", e))
+}
+
+/// Get the master password via invisible interactive input or from stdin.
+fn get_master_password() -> Result<String, Error> {
+    read_master_password(stdin().is_terminal())
+}
+
+/// Get the master password from the terminal, even if stdin is redirected, or else from stdin.
+fn read_master_password(from_terminal: bool) -> Result<String, Error> {
+    let master_password = if from_terminal {
+        // prompt the user, the terminal doesn't have to be stdin
+        prompt_password("Master password: ")
+            .map_err(|e| Error::Input(format!("Cannot read master password: {}", e)))?
+    } else {
+        // If we're not on a terminal, read from stdin
+        read_stdin_line("master password")?
+    };
+    if master_password.is_empty() {
+        return Err(Error::Input("No master password given".to_string()));
+    }
+    Ok(master_password)
+}
+
+/// Calculate salted hash of the master password and the domain, skipping revoked iterations.
+fn get_derived_key(
+    domain: &str,
+    master_password: &str,
+    settings: &Settings,
+    revoked_pw_hashes: &RevocationSet,
+) -> DerivedKey {
+    let user = settings.user.as_deref();
+    let derived = derive_key(master_password, domain, user, revoked_pw_hashes);
+    for pw_revocation_hash in &derived.skipped {
+        info!("hash:{} is revoked", pw_revocation_hash);
+    }
+    verbose!("Iteration: {}", derived.iteration);
+    derived
+}
+
+/// Parse the number of words given on the command line
+fn parse_amount_words(arg: Option<String>) -> Result<u8, Error> {
+    let arg = arg.ok_or_else(|| Error::Usage("Expecting argument: Number of words".to_string()))?;
+    match arg.parse() {
+        Ok(0) | Err(_) => Err(Error::Usage(format!(
+            "Number of words must be between 1 and 255, got {}",
+            arg
+        ))),
+        Ok(amount_words) => Ok(amount_words),
+    }
+}
+
+/// Parse an option value given on the command line.
+fn parse_option<T: FromStr>(arg: Option<String>, name: &str) -> Result<T, Error>
+where
+    T::Err: fmt::Display,
+{
+    let arg = arg.ok_or_else(|| Error::Usage(format!("Expecting argument: {}", name)))?;
+    arg.parse()
+        .map_err(|e| Error::Usage(format!("Invalid {} {}: {}", name, arg, e)))
+}
+
+/// Load the configuration file, or an empty configuration if there is none.
+///
+/// Unless domains are raw, table names and aliases are canonicalized like the domains looked up.
+fn get_config(raw_domain: bool) -> Result<Config, Error> {
+    // without a configuration directory there is no configuration
+    let Some(path) = Config::default_path() else {
+        verbose!("Configuration file: none, neither XDG_CONFIG_HOME nor HOME is set");
+        return Ok(Config::default());
+    };
+    verbose!("Configuration file: {}", path.display());
+    let config = Config::load(&path).map_err(|errors| {
+        for e in &errors {
+            eprintln!("{}: {}", path.display(), e);
         }
+        Error::Config(format!("Invalid configuration file {}", path.display()))
+    })?;
+    Ok(if raw_domain {
+        config
+    } else {
+        config.canonicalize()
+    })
+}
+
+/// Look up the domain in the configuration and apply the command line options.
+///
+/// Returns the domain to derive the password for and the settings to use.
+fn get_settings(
+    domain: String,
+    config: &Config,
+    cli: &Profile,
+) -> Result<(String, Settings), Error> {
+    let (target, mut profile) = config
+        .resolve(&domain)
+        .map_err(|e| Error::Config(format!("Invalid configuration: {}", e)))?;
+    profile.overlay(cli);
+    let settings = profile
+        .settings()
+        .map_err(|e| Error::Config(format!("Invalid settings for {}: {}", domain, e)))?;
+
+    if target != domain {
+        info!("Domain: {} is an alias of {}", domain, target);
+    }
+    if let Some(user) = &profile.user {
+        info!("User: {}", user);
+    }
+    if let Some(notes) = &profile.notes {
+        info!("Notes: {}", notes);
+    }
+    Ok((target, settings))
+}
+
+/// Choose the words of a password, applying the policy.
+fn get_password(password_str: &str, settings: &Settings) -> Result<Password, Error> {
+    password_from_key(password_str, settings)
+        .map_err(|e| Error::Policy(format!("Cannot apply password policy: {}", e)))
+}
+
+/// A password of a domain and how it has been derived.
+struct DerivedPassword {
+    iteration: u64,
+    /// Number of revoked iterations that have been skipped, unknown for a given iteration
+    skipped: Option<usize>,
+    password: String,
+    /// Number of words and their entropy in bits, after applying the policy
+    words: usize,
+    entropy_bits: f32,
+}
+
+impl DerivedPassword {
+    /// Apply the settings to a derived key.
+    fn new(derived: &DerivedKey, settings: &Settings) -> Result<Self, Error> {
+        let password = get_password(&derived.key, settings)?;
+        Ok(Self::of_iteration(
+            derived.iteration,
+            Some(derived.skipped.len()),
+            password,
+        ))
+    }
+
+    /// A password of an iteration.
+    fn of_iteration(iteration: u64, skipped: Option<usize>, password: Password) -> Self {
+        Self {
+            iteration,
+            skipped,
+            words: password.words(),
+            entropy_bits: password.entropy_bits(),
+            password: password.to_string(),
+        }
+    }
+}
+
+/// Generate xkcdget password.
+fn xkcdget(
+    domain: String,
+    settings: &Settings,
+    store: &mut LayeredRevocationStore,
+) -> Result<DerivedPassword, Error> {
+    let master_password = get_master_password()?;
+    unlock_if_authenticated(&master_password, store)?;
+    let revoked_pw_hashes = get_revoked_pw_hashes(store)?;
+    let derived = get_derived_key(&domain, &master_password, settings, &revoked_pw_hashes);
+    DerivedPassword::new(&derived, settings)
+}
+
+/// Generate the password of a specific iteration, regardless of revocations.
+fn xkcdget_iteration(
+    domain: String,
+    settings: &Settings,
+    iteration: u64,
+    store: &mut LayeredRevocationStore,
+) -> Result<DerivedPassword, Error> {
+    let master_password = get_master_password()?;
+    unlock_if_authenticated(&master_password, store)?;
+    let user = settings.user.as_deref();
+    let password_str = derive_key_iteration(&master_password, &domain, user, iteration);
+    let revoked_pw_hashes = get_revoked_pw_hashes(store)?;
+    if revoked_pw_hashes.contains(&revocation_hash(&password_str)) {
+        info!("Iteration {} has been revoked", iteration);
+    }
+    let password = get_password(&password_str, settings)?;
+    Ok(DerivedPassword::of_iteration(iteration, None, password))
+}
+
+/// An iteration in the history of a domain.
+struct HistoryEntry {
+    iteration: u64,
+    /// Revocation of the iteration, none for the active one
+    revocation: Option<RevocationEntry>,
+    /// Password, if asked for
+    password: Option<String>,
+    /// Entropy of the password in bits
+    entropy_bits: f32,
+}
+
+/// Collect the iterations of a domain up to the active one, optionally with passwords.
+fn history(
+    domain: String,
+    settings: &Settings,
+    show_passwords: bool,
+    store: &mut LayeredRevocationStore,
+) -> Result<Vec<HistoryEntry>, Error> {
+    let master_password = get_master_password()?;
+    unlock_if_authenticated(&master_password, store)?;
+    let entries = store
+        .entries()
+        .map_err(|e| Error::revocation("Error opening revocation file", e))?;
+    let revoked_pw_hashes: RevocationSet = entries.iter().map(|e| e.hash.clone()).collect();
+    let user = settings.user.as_deref();
+    let mut history = Vec::new();
+    for key in key_history(&master_password, &domain, user, &revoked_pw_hashes) {
+        let password = get_password(&key.key, settings)?;
+        history.push(HistoryEntry {
+            iteration: key.iteration,
+            revocation: entries.iter().find(|e| e.hash == key.hash).cloned(),
+            password: show_passwords.then(|| password.to_string()),
+            entropy_bits: password.entropy_bits(),
+        });
+    }
+    Ok(history)
+}
+
+/// Options of the revoke action.
+#[derive(Default)]
+struct RevokeOptions {
+    reason: Option<Reason>,
+    label: Option<String>,
+    /// Only show what would be revoked
+    dry_run: bool,
+}
+
+/// A revoked password and the one replacing it.
+struct Revocation {
+    hash: String,
+    revoked: DerivedPassword,
+    /// Next password, if asked for
+    next: Option<DerivedPassword>,
+}
+
+/// Generate and revoke a password, optionally deriving the new password
+fn revoke(
+    domain: String,
+    settings: &Settings,
+    options: RevokeOptions,
+    show_passwords: bool,
+    store: &mut LayeredRevocationStore,
+) -> Result<Revocation, Error> {
+    let master_password = get_master_password()?;
+    let label_key = unlock_if_authenticated(&master_password, store)?;
+    let mut revoked_pw_hashes = get_revoked_pw_hashes(store)?;
+    let derived = get_derived_key(&domain, &master_password, settings, &revoked_pw_hashes);
+    let pw_revocation_hash = revocation_hash(&derived.key);
+    if options.dry_run {
+        info!("Would revoke hash:{}", pw_revocation_hash);
+    } else {
+        info!("Revoking hash:{}", pw_revocation_hash);
+    }
+
+    let next = if show_passwords {
+        revoked_pw_hashes.insert(&pw_revocation_hash);
+        let user = settings.user.as_deref();
+        let next = derive_key(&master_password, &domain, user, &revoked_pw_hashes);
+        Some(DerivedPassword::new(&next, settings)?)
+    } else {
+        None
+    };
+    let revocation = Revocation {
+        hash: pw_revocation_hash.clone(),
+        revoked: DerivedPassword::new(&derived, settings)?,
+        next,
+    };
+
+    if options.dry_run {
+        return Ok(revocation);
+    }
+
+    // encrypt label so that the revocation file doesn't reveal the account
+    let label = options.label.map(|label| {
+        let label_key = label_key.unwrap_or_else(|| LabelKey::derive(&master_password));
+        label_key.encrypt(&pw_revocation_hash, &label)
     });
 
-    // return iterator over password hashes
-    file_content
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect()
+    // add entry to revocation file
+    let entry = RevocationEntry::new(pw_revocation_hash, options.reason, label);
+    store
+        .revoke(entry)
+        .map_err(|e| Error::revocation("Can't append revocation hash to revocation file", e))?;
+    Ok(revocation)
 }
 
-/// Get the master password via invisible interactive input or from stdin.
-fn get_master_password() -> String {
-    // Get master password
-    if stdin().is_terminal() {
-        // If we're on a terminal, prompt the user
-        prompt_password("Master password: ").expect("Could not read master password from terminal")
+/// Undo the latest revocation of a domain
+fn unrevoke(
+    domain: String,
+    settings: &Settings,
+    store: &mut LayeredRevocationStore,
+) -> Result<(), Error> {
+    let master_password = get_master_password()?;
+    unlock_if_authenticated(&master_password, store)?;
+    let revoked_pw_hashes = get_revoked_pw_hashes(store)?;
+    let user = settings.user.as_deref();
+    let derived = derive_key(&master_password, &domain, user, &revoked_pw_hashes);
+
+    // the iterations before the current one have all been revoked
+    let Some(pw_revocation_hash) = derived.skipped.last() else {
+        return Err(Error::Unrevoke(format!(
+            "No password of {} has been revoked",
+            domain
+        )));
+    };
+    info!("Unrevoking hash:{}", pw_revocation_hash);
+    // removing the hash from the revocation file wouldn't help if a source revokes it too
+    for source in store.read_only() {
+        let revoked = get_revoked_pw_hashes(source)?;
+        if revoked.contains(pw_revocation_hash) {
+            return Err(Error::Unrevoke(format!(
+                "The hash is revoked in the read-only revocation file {}",
+                source.path().display()
+            )));
+        }
+    }
+    let removed = store
+        .unrevoke(pw_revocation_hash)
+        .map_err(|e| Error::revocation("Can't remove revocation hash from revocation file", e))?;
+    if removed == 0 || get_revoked_pw_hashes(store)?.contains(pw_revocation_hash) {
+        return Err(Error::Unrevoke(
+            "The hash is still revoked in a read-only revocation file".to_string(),
+        ));
+    }
+    info!(
+        "Iteration {} is active now (was {})",
+        derived.iteration - 1,
+        derived.iteration
+    );
+    Ok(())
+}
+
+/// Format seconds since the Unix epoch as UTC date and time.
+fn format_time(secs: u64) -> String {
+    // civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
     } else {
-        // If we're not on a terminal, read from stdin
-        let mut buf = String::with_capacity(64);
-        stdin()
-            .lock()
-            .read_line(&mut buf)
-            .expect("We're not on a terminal, but no password was provided on stdin");
-        String::from(buf.trim())
-    }
-}
-
-/// Query for the master password and calculate salted hash of it and the domain.
-fn get_scrypt_z85(domain: String) -> String {
-    let master_password = get_master_password();
-
-    // hash password until one is found that has not been revoked
-    let mut password = [0; KEY_LEN];
-    let (log_n, r, p) = (16, 8, 16);
-    let scrypt_params = Params::new(log_n, r, p, KEY_LEN).expect("Cannot create scrypt parameters");
-    let revoked_pw_hashes: Vec<String> = get_revoked_pw_hashes();
-    for iteration in 0.. {
-        // get password for this iteration
-        let salt = format!("{}:{}", iteration, domain);
-        scrypt(
-            master_password.as_bytes(),
-            salt.as_bytes(),
-            &scrypt_params,
-            &mut password,
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let time_of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+/// List the entries of the revocation file, decrypting labels if asked to.
+fn revocations_list(show_labels: bool, store: &mut LayeredRevocationStore) -> Result<(), Error> {
+    let label_key = if show_labels || revocation_files_authenticated(store, &[])? {
+        Some(unlock_revocation_files(
+            &get_master_password()?,
+            store,
+            &mut [],
+        ))
+    } else {
+        None
+    };
+    let entries = store
+        .entries()
+        .map_err(|e| Error::revocation("Error opening revocation file", e))?;
+    let label_key = label_key.filter(|_| show_labels);
+    for entry in entries {
+        let time = entry.time.map_or("-".to_string(), format_time);
+        let reason = entry.reason.map_or("-", Reason::as_str);
+        let label = match (&entry.label, &label_key) {
+            (None, _) => String::new(),
+            (Some(_), None) => "(encrypted label)".to_string(),
+            (Some(label), Some(key)) => key
+                .decrypt(&entry.hash, label)
+                .unwrap_or_else(|| "(cannot decrypt label)".to_string()),
+        };
+        let line = format!("{:<19}  {:<11}  {}  {}", time, reason, entry.hash, label);
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// Open other revocation files, which have to exist, and unlock them if needed
+fn open_revocation_files(
+    paths: &[String],
+    store: &mut LayeredRevocationStore,
+) -> Result<Vec<FileRevocationStore>, Error> {
+    let mut others: Vec<FileRevocationStore> = paths.iter().map(FileRevocationStore::new).collect();
+    for other in &others {
+        if !other.path().exists() {
+            return Err(Error::Revocation(format!(
+                "Revocation file {} doesn't exist",
+                other.path().display()
+            )));
+        }
+    }
+    if revocation_files_authenticated(store, &others)? {
+        unlock_revocation_files(&get_master_password()?, store, &mut others);
+    }
+    Ok(others)
+}
+
+/// Read the entries of another revocation file
+fn read_revocation_file(other: &FileRevocationStore) -> Result<Vec<RevocationEntry>, Error> {
+    other.entries().map_err(|e| {
+        Error::revocation(
+            &format!("Error reading revocation file {}", other.path().display()),
+            e,
         )
-        .unwrap();
-        let password_str = z85::encode(password);
+    })
+}
+
+/// Merge other revocation files into the local one
+fn revocations_merge(paths: &[String], store: &mut LayeredRevocationStore) -> Result<(), Error> {
+    if paths.is_empty() {
+        return Err(Error::Usage(
+            "Expecting revocation files to merge".to_string(),
+        ));
+    }
+    let others = open_revocation_files(paths, store)?;
+    let mut other = Vec::new();
+    for file in &others {
+        other.extend(read_revocation_file(file)?);
+    }
+    let changes = store
+        .merge(&other)
+        .map_err(|e| Error::revocation("Error merging into revocation file", e))?;
+    info!(
+        "Merged {} entries into {}, {} added or updated",
+        other.len(),
+        store.writable().path().display(),
+        changes
+    );
+    Ok(())
+}
 
-        // if the password has been revoked do another round, else return it
-        let pw_revocation_hash = get_revocation_hash(&password_str);
-        if revoked_pw_hashes.contains(&pw_revocation_hash) {
-            eprintln!("hash:{} is revoked", pw_revocation_hash);
-        } else {
-            return password_str;
+/// Show entries only in the local revocation file (`<`) or only in the other one (`>`)
+///
+/// Returns whether the files differ.
+fn revocations_diff(path: &str, store: &mut LayeredRevocationStore) -> Result<bool, Error> {
+    let others = open_revocation_files(&[path.to_string()], store)?;
+    let local = store
+        .entries()
+        .map_err(|e| Error::revocation("Error opening revocation file", e))?;
+    let other = read_revocation_file(&others[0])?;
+    let (only_local, only_other) = diff_revocation_lists(&local, &other);
+    for entry in &only_local {
+        println!("< {}", entry.to_line());
+    }
+    for entry in &only_other {
+        println!("> {}", entry.to_line());
+    }
+    Ok(!only_local.is_empty() || !only_other.is_empty())
+}
+
+/// Print the revoked hashes as a string for transfer to another device, optionally as QR code
+fn revocations_export(show_qr: bool, store: &mut LayeredRevocationStore) -> Result<(), Error> {
+    if revocation_files_authenticated(store, &[])? {
+        unlock_revocation_files(&get_master_password()?, store, &mut []);
+    }
+    let entries = store
+        .entries()
+        .map_err(|e| Error::revocation("Error opening revocation file", e))?;
+    let transfers = export_revocations(&entries)
+        .map_err(|e| Error::Transfer(format!("Cannot export revocations: {}", e)))?;
+    for (i, transfer) in transfers.iter().enumerate() {
+        if transfers.len() > 1 {
+            info!("String {} of {}", i + 1, transfers.len());
+        }
+        if show_qr {
+            let code = QrCode::new(transfer).map_err(|e| {
+                Error::Transfer(format!(
+                    "Cannot create QR code, use the string instead: {}",
+                    e
+                ))
+            })?;
+            // inverted, because terminals usually draw light characters on a dark background
+            let image = code
+                .render::<Dense1x2>()
+                .dark_color(Dense1x2::Light)
+                .light_color(Dense1x2::Dark)
+                .build();
+            println!("{}", image);
         }
+        println!("{}", transfer);
     }
+    Ok(())
+}
 
-    unreachable!("The unconditional loop above must return the first non-revoked password hash");
+/// Merge the revoked hashes of transfer strings, given as arguments or on stdin
+fn revocations_import(
+    mut transfers: Vec<String>,
+    store: &mut LayeredRevocationStore,
+) -> Result<(), Error> {
+    let from_stdin = transfers.is_empty();
+    let entries = loop {
+        let mut end = !from_stdin;
+        if from_stdin {
+            if stdin().is_terminal() {
+                eprint!("Revocations: ");
+                // the prompt is only cosmetic
+                stderr().flush().ok();
+            }
+            match read_stdin_line("revocation string") {
+                Ok(transfer) => transfers.push(transfer),
+                Err(e) if transfers.is_empty() => return Err(e),
+                // report which strings of the export are missing
+                Err(_) => end = true,
+            }
+        }
+        match import_revocations(&transfers) {
+            // read the remaining strings of the export
+            Err(TransferError::MissingStrings(..)) if !end => continue,
+            result => {
+                break result
+                    .map_err(|e| Error::Transfer(format!("Cannot import revocations: {}", e)))?
+            }
+        }
+    };
+    if revocation_files_authenticated(store, &[])? {
+        unlock_revocation_files(&get_master_password()?, store, &mut []);
+    }
+    let changes = store
+        .merge(&entries)
+        .map_err(|e| Error::revocation("Error merging into revocation file", e))?;
+    info!(
+        "Imported {} hashes into {}, {} added",
+        entries.len(),
+        store.writable().path().display(),
+        changes
+    );
+    Ok(())
+}
+
+/// Convert the revocation file to an authenticated one
+fn revocations_authenticate(store: &mut LayeredRevocationStore) -> Result<(), Error> {
+    unlock_revocation_files(&get_master_password()?, store, &mut []);
+    let writable = store.writable_mut();
+    writable
+        .authenticate()
+        .map_err(|e| Error::revocation("Error authenticating revocation file", e))?;
+    info!("Authenticated {}", writable.path().display());
+    Ok(())
+}
+
+/// Validate the configuration file and report all errors.
+///
+/// Returns whether the configuration is valid.
+fn config_check() -> Result<bool, Error> {
+    let path = Config::default_path().ok_or_else(home_unset)?;
+    let errors = match Config::load(&path) {
+        Ok(config) => {
+            let errors = config.check();
+            if errors.is_empty() {
+                info!("{}: OK ({} sites)", path.display(), config.sites.len());
+            }
+            errors
+        }
+        Err(errors) => errors,
+    };
+    for e in &errors {
+        eprintln!("{}: {}", path.display(), e);
+    }
+    Ok(errors.is_empty())
+}
+
+/// What to do with the password of a domain.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Get,
+    Revoke,
+    Unrevoke,
+    History,
+    Batch,
+}
+
+impl Action {
+    /// Name of the command of the action.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Get => "get",
+            Self::Revoke => "revoke",
+            Self::Unrevoke => "unrevoke",
+            Self::History => "history",
+            Self::Batch => "batch",
+        }
+    }
+}
+
+const USAGE: &str = "\
+Usage: xkcdget [COMMAND] [OPTIONS] [--] [DOMAIN]
+
+Without a domain, it is read from stdin. The master password is read from
+the terminal, or from stdin if that isn't a terminal.
+
+Commands:
+  get                          Print the password of a domain (default)
+  revoke                       Revoke the password of a domain
+  unrevoke                     Undo the latest revocation of a domain
+  history                      List the iterations of a domain
+  batch [FILE]                 Print the passwords of the domains in FILE or on stdin
+  revocations list [--labels]  List the revocations, optionally decrypting labels
+  revocations merge FILE...    Merge other revocation files into the revocation file
+  revocations diff FILE        Show entries only in the revocation file or in FILE
+  revocations authenticate     Protect the revocation file with MACs
+  revocations export [--qr]    Print the revoked hashes for transfer to another device
+  revocations import [STR...]  Merge exported hashes into the revocation file
+  config check                 Validate the configuration file
+  help                         Show this help
+
+Password options:
+  -w, --words N                Number of words
+  -l, --list NAME              Built-in word list
+      --wordlist FILE          Word list file with one word per line
+  -u, --user NAME              User name, for several accounts on a domain
+      --min-length N           Minimum number of characters
+      --max-length N           Maximum number of characters
+      --symbols SET            Allowed symbols, the first one is used
+      --digits N               Number of digits
+      --separator SEP          Separator between words
+      --case camel|lower|upper Capitalization of the words
+      --raw-domain             Don't normalize the domain
+
+Command options:
+  -i, --iteration N            get: password of an iteration, regardless of revocations
+      --reason REASON          revoke: compromised, rotation or policy
+      --label TEXT             revoke: encrypted label of the revocation
+  -n, --dry-run                revoke: don't write the revocation file
+      --passwords              revoke, history: show passwords
+      --format text|json|env   get, revoke, history, batch: output format, env not for
+                               history and batch
+  -0, --null                   batch: domains are separated by NUL, not newlines
+      --from-config            batch: the domains of the configuration file
+
+Output options:
+  -q, --quiet                  Only report errors on stderr
+  -v, --verbose                Also report the files used and the iteration
+
+Revocation file options:
+      --revocation-file FILE   Revocation file to read and write
+      --revocation-source FILE Additional read-only revocation file, repeatable
+      --accept-revocation-loss Accept a missing revocation file, lost entries or
+                               lost authentication
+
+  -h, --help                   Show this help
+  -V, --version                Show the version
+
+Exit status:
+  0  success
+  1  revocations diff found differences, config check found errors, or batch
+     failed for some domains
+  2  invalid command line
+  3  domain, master password or revocation string missing or unreadable
+  4  HOME unset or invalid
+  5  invalid configuration file or settings
+  6  revocation file unreadable, unwritable, corrupt, not authentic or with lost entries
+  7  password policy can't be satisfied
+  8  revocation string can't be exported or imported
+  9  no revocation that can be undone
+";
+
+/// Reject arguments left after a command that takes none
+fn expect_no_arguments(
+    args: &mut impl Iterator<Item = String>,
+    command: &str,
+) -> Result<(), Error> {
+    match args.next() {
+        None => Ok(()),
+        Some(arg) => Err(Error::Usage(format!(
+            "Unexpected argument for {}: {}",
+            command, arg
+        ))),
+    }
+}
+
+/// Parse and run a revocations command
+///
+/// Returns whether the command succeeded, which is false for differing files.
+fn revocations_command(
+    args: Vec<String>,
+    store: &mut LayeredRevocationStore,
+) -> Result<bool, Error> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or_else(|| {
+        Error::Usage(
+            "Expecting revocations command: list, merge, diff, authenticate, export or import"
+                .to_string(),
+        )
+    })?;
+    // flags of the commands, everything else is a file or string
+    let mut flag = |name: &str| match args.next() {
+        None => Ok(false),
+        Some(arg) if arg == name => expect_no_arguments(&mut args, &command).map(|_| true),
+        Some(arg) => Err(Error::Usage(format!(
+            "Unexpected argument for {}: {}",
+            command, arg
+        ))),
+    };
+    match command.as_str() {
+        "list" => revocations_list(flag("--labels")?, store)?,
+        "export" => revocations_export(flag("--qr")?, store)?,
+        "authenticate" => {
+            expect_no_arguments(&mut args, &command)?;
+            revocations_authenticate(store)?
+        }
+        "merge" => {
+            let paths: Vec<String> = args.filter(|arg| arg != "--").collect();
+            revocations_merge(&paths, store)?
+        }
+        "diff" => {
+            let mut args = args.filter(|arg| arg != "--");
+            let path = args.next().ok_or_else(|| {
+                Error::Usage("Expecting revocation file to compare with".to_string())
+            })?;
+            expect_no_arguments(&mut args, &command)?;
+            return revocations_diff(&path, store).map(|differ| !differ);
+        }
+        "import" => {
+            let transfers = args.filter(|arg| arg != "--").collect();
+            revocations_import(transfers, store)?
+        }
+        _ => {
+            return Err(Error::Usage(format!(
+                "Unknown revocations command: {}",
+                command
+            )))
+        }
+    }
+    Ok(true)
+}
+
+/// Format of the output of the password commands.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Env,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "env" => Ok(Self::Env),
+            _ => Err("expected text, json or env".to_string()),
+        }
+    }
+}
+
+/// Value of a field of structured output.
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    List(Vec<Record>),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Self::Number(value.to_string())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Self::Number(value.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Self::Number(value.to_string())
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Self::Number(value.to_string())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<Vec<Record>> for Value {
+    fn from(value: Vec<Record>) -> Self {
+        Self::List(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+/// Quote a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Quote a string for the shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+impl Value {
+    fn to_json(&self) -> String {
+        match self {
+            Self::Null => "null".to_string(),
+            Self::Bool(value) => value.to_string(),
+            Self::Number(value) => value.clone(),
+            Self::String(value) => json_string(value),
+            Self::List(records) => {
+                let records: Vec<String> = records.iter().map(Record::to_json).collect();
+                format!("[{}]", records.join(","))
+            }
+        }
+    }
+}
+
+/// Fields of structured output, in order.
+#[derive(Default)]
+struct Record(Vec<(&'static str, Value)>);
+
+impl Record {
+    /// Add a field.
+    fn with(mut self, key: &'static str, value: impl Into<Value>) -> Self {
+        self.0.push((key, value.into()));
+        self
+    }
+
+    /// The record as a JSON object on one line.
+    fn to_json(&self) -> String {
+        let fields: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{}:{}", json_string(key), value.to_json()))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// The record as `XKCDGET_KEY=value` lines for the shell, without lists.
+    fn to_env(&self) -> String {
+        let mut env = String::new();
+        for (key, value) in &self.0 {
+            let value = match value {
+                Value::Null => String::new(),
+                Value::Bool(value) => value.to_string(),
+                Value::Number(value) => value.clone(),
+                Value::String(value) => shell_quote(value),
+                Value::List(_) => continue,
+            };
+            env.push_str(&format!("XKCDGET_{}={}\n", key.to_uppercase(), value));
+        }
+        env
+    }
+
+    /// Print the record in a structured format.
+    fn print(&self, format: Format) {
+        match format {
+            Format::Json => println!("{}", self.to_json()),
+            Format::Env => print!("{}", self.to_env()),
+            Format::Text => unreachable!("Text output has no records"),
+        }
+    }
 }
 
-/// Generate and print xkcdget password.
-fn xkcdget(domain: String) -> String {
-    // assert word list length so that we don't forget to change this code when
-    // word list length changes.
-    assert!(WORDLIST.len() == WORDLIST_LEN);
+/// Fields describing how the passwords of a domain are derived.
+fn domain_record(raw_domain: &str, domain: &str, settings: &Settings) -> Record {
+    let user = settings.user.as_deref();
+    Record::default()
+        .with("domain", raw_domain)
+        .with("canonical_domain", domain)
+        .with("user", user)
+        .with("wordlist", settings.wordlist.name())
+        .with("wordlist_fingerprint", settings.wordlist.fingerprint())
+        .with("scheme_version", salt_version(user))
+}
 
-    // get password bits
-    let password_str = get_scrypt_z85(domain);
+/// Print the password of a domain.
+fn print_password(derived: &DerivedPassword, format: Format, record: Record) {
+    match format {
+        Format::Text => println!("{}", derived.password),
+        _ => record
+            .with("entropy_bits", derived.entropy_bits)
+            .with("iteration", derived.iteration)
+            .with("skipped", derived.skipped)
+            .with("password", derived.password.as_str())
+            .print(format),
+    }
+}
 
-    // choose words
-    let mut words = Vec::new();
-    for i in 0..AMOUNT_WORDS {
-        let offset = 10 * i as usize;
+/// Print a revocation: the old password to stderr, the new one to stdout, like a password that is generated.
+fn print_revocation(revocation: &Revocation, dry_run: bool, format: Format, record: Record) {
+    let next = revocation.next.as_ref();
+    match format {
+        Format::Text => {
+            let Some(next) = next else {
+                return;
+            };
+            eprintln!("Old password: {}", revocation.revoked.password);
+            if reports(Verbosity::Normal) {
+                eprint!("New password: ");
+                // the prompt is only cosmetic
+                stderr().flush().ok();
+            }
+            println!("{}", next.password);
+        }
+        _ => record
+            .with("revoked_hash", revocation.hash.as_str())
+            .with("dry_run", dry_run)
+            .with("entropy_bits", revocation.revoked.entropy_bits)
+            .with("iteration", revocation.revoked.iteration)
+            .with("skipped", revocation.revoked.skipped)
+            .with(
+                "password",
+                next.map(|_| revocation.revoked.password.as_str()),
+            )
+            .with("new_entropy_bits", next.map(|next| next.entropy_bits))
+            .with("new_iteration", next.map(|next| next.iteration))
+            .with("new_password", next.map(|next| next.password.as_str()))
+            .print(format),
+    }
+}
 
-        // z85 consumes 5 bytes at a time and decodes them into 4 bytes (32 bits).
-        // decode 64 bits
-        let key = z85::decode(&password_str[offset..(offset + 10)])
-            .expect("Can't z85-decode password_str");
-        assert!(key.len() == 8); // key:[u8;8] would be the bigger hassle
+/// Print the history of a domain, as table or JSON.
+fn print_history(history: &[HistoryEntry], format: Format, record: Record) {
+    if format == Format::Text {
+        for entry in history {
+            let status = match &entry.revocation {
+                None => "active".to_string(),
+                Some(revocation) => {
+                    let time = revocation.time.map_or("-".to_string(), format_time);
+                    let reason = revocation.reason.map_or("-", Reason::as_str);
+                    format!("revoked {} {}", time, reason)
+                }
+            };
+            let password = entry.password.as_deref().unwrap_or_default();
+            let line = format!("{:>3}  {:<39}  {}", entry.iteration, status, password);
+            println!("{}", line.trim_end());
+        }
+        return;
+    }
+    let active = history
+        .last()
+        .expect("History ends with the active iteration");
+    let iterations: Vec<Record> = history
+        .iter()
+        .map(|entry| {
+            let revocation = entry.revocation.as_ref();
+            Record::default()
+                .with("iteration", entry.iteration)
+                .with(
+                    "status",
+                    if revocation.is_some() {
+                        "revoked"
+                    } else {
+                        "active"
+                    },
+                )
+                .with("revoked_at", revocation.and_then(|r| r.time))
+                .with(
+                    "reason",
+                    revocation.and_then(|r| r.reason).map(Reason::as_str),
+                )
+                .with("password", entry.password.as_deref())
+        })
+        .collect();
+    record
+        .with("entropy_bits", active.entropy_bits)
+        .with("iteration", active.iteration)
+        .with("skipped", history.len() - 1)
+        .with("iterations", iterations)
+        .print(format);
+}
 
-        // read decoded bytes into u64
-        let int_key: u64 = u64::from_le_bytes(
-            key.as_slice()[0..8]
-                .try_into()
-                .expect("Cannot convert slice to u64"),
+/// Canonicalize the domain unless raw and look up its settings.
+///
+/// Returns the domain to derive the password for, its settings and the fields describing them.
+fn resolve_domain(
+    domain: String,
+    raw_domain: bool,
+    config: &Config,
+    cli: &Profile,
+) -> Result<(String, Settings, Record), Error> {
+    let given_domain = domain.clone();
+    let mut domain = domain;
+    if !raw_domain {
+        let canonical = canonical_domain(&domain);
+        if canonical != domain {
+            info!("Domain: {}", canonical);
+        }
+        domain = canonical;
+    }
+    // normalization leaves nothing of input like "https://"
+    if domain.is_empty() {
+        return Err(Error::Input("No domain given".to_string()));
+    }
+    let (domain, settings) = get_settings(domain, config, cli)?;
+    let record = domain_record(&given_domain, &domain, &settings);
+    Ok((domain, settings, record))
+}
+
+/// Whether the path of a file of domains means stdin.
+fn path_is_stdin(path: Option<&str>) -> bool {
+    matches!(path, None | Some("-"))
+}
+
+/// Read domains separated by newlines or NUL characters from a file or stdin.
+fn read_batch_domains(path: Option<&str>, null_separated: bool) -> Result<Vec<String>, Error> {
+    let content = match path {
+        Some(path) if !path_is_stdin(Some(path)) => read_to_string(path),
+        _ => {
+            let mut content = String::new();
+            stdin().lock().read_to_string(&mut content).map(|_| content)
+        }
+    }
+    .map_err(|e| Error::Input(format!("Cannot read domains: {}", e)))?;
+    let separator = if null_separated { '\0' } else { '\n' };
+    Ok(content
+        .split(separator)
+        .map(str::trim)
+        .filter(|domain| !domain.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Domains of the configuration file, without aliases.
+fn config_domains(config: &Config) -> Vec<String> {
+    config
+        .sites
+        .iter()
+        .filter(|(_, profile)| profile.alias.is_none())
+        .map(|(domain, _)| domain.clone())
+        .collect()
+}
+
+/// Derive and print the passwords of many domains with one master password.
+///
+/// A failure for one domain is reported and the others are still derived.
+/// The word list is only reported when it changes.
+/// Returns whether all domains succeeded.
+fn batch(
+    domains: &[String],
+    master_password: &str,
+    cli: &Profile,
+    raw_domain: bool,
+    config: &Config,
+    format: Format,
+    store: &mut LayeredRevocationStore,
+) -> Result<bool, Error> {
+    unlock_if_authenticated(master_password, store)?;
+    let revoked_pw_hashes = get_revoked_pw_hashes(store)?;
+    let mut succeeded = true;
+    let mut wordlist = None;
+    for given_domain in domains {
+        let derived = resolve_domain(given_domain.clone(), raw_domain, config, cli).and_then(
+            |(domain, settings, record)| {
+                let fingerprint = Some(settings.wordlist.fingerprint());
+                if wordlist != fingerprint {
+                    print_wordlist(&settings);
+                    wordlist = fingerprint;
+                }
+                let derived =
+                    get_derived_key(&domain, master_password, &settings, &revoked_pw_hashes);
+                let derived = DerivedPassword::new(&derived, &settings)?;
+                print_entropy(&derived, &settings);
+                Ok((derived, record))
+            },
         );
+        match derived {
+            Ok((derived, _)) if format == Format::Text => {
+                println!("{}\t{}", given_domain, derived.password)
+            }
+            Ok((derived, record)) => print_password(&derived, format, record),
+            Err(e) => {
+                eprintln!("Error: {}: {}", given_domain, e);
+                succeeded = false;
+            }
+        }
+    }
+    Ok(succeeded)
+}
 
-        // choose word
-        let index = (int_key as usize) % WORDLIST_LEN;
-        let word_uncap = WORDLIST[index];
+/// Options of the password commands that take a value, which may look like a global option
+const PASSWORD_VALUE_OPTIONS: &[&str] = &[
+    "--reason",
+    "--label",
+    "-i",
+    "--iteration",
+    "--format",
+    "-w",
+    "--words",
+    "--wordlist",
+    "-u",
+    "--user",
+    "-l",
+    "--list",
+    "--min-length",
+    "--max-length",
+    "--symbols",
+    "--digits",
+    "--separator",
+    "--case",
+];
 
-        // capitalize word
-        let mut word_chars = word_uncap.chars();
-        let first_char = word_chars.next().expect("Chosen word is empty");
-        let rest_chars = word_chars.flat_map(|c| c.to_lowercase());
-        let word: String = first_char.to_uppercase().chain(rest_chars).collect();
+/// Parse and run a command about the password of a domain
+///
+/// Returns whether the command succeeded, which is false if a batch failed for some domains.
+fn password_command(
+    mut action: Action,
+    args: Vec<String>,
+    store: &mut LayeredRevocationStore,
+) -> Result<bool, Error> {
+    let mut cli = Profile::default();
+    let mut revoke_options = RevokeOptions::default();
+    let mut iteration = None;
+    let mut show_passwords = false;
+    let mut raw_domain = false;
+    let mut format = Format::Text;
+    let mut null_separated = false;
+    let mut from_config = false;
+    let mut domain = None;
+    // options only valid for some actions, checked once the action is known
+    let mut restricted: Vec<(&str, &[Action])> = Vec::new();
 
-        // add word
-        words.push(word);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // legacy flag for the revoke command
+            "-r" | "--revoke" if action == Action::Get => action = Action::Revoke,
+            // command options
+            "--reason" => {
+                revoke_options.reason = Some(parse_option(args.next(), "reason")?);
+                restricted.push(("--reason", &[Action::Revoke]));
+            }
+            "--label" => {
+                revoke_options.label = Some(parse_option(args.next(), "label")?);
+                restricted.push(("--label", &[Action::Revoke]));
+            }
+            "-n" | "--dry-run" => {
+                revoke_options.dry_run = true;
+                restricted.push(("--dry-run", &[Action::Revoke]));
+            }
+            "-i" | "--iteration" => {
+                iteration = Some(parse_option(args.next(), "iteration")?);
+                restricted.push(("--iteration", &[Action::Get]));
+            }
+            "--passwords" => {
+                show_passwords = true;
+                restricted.push(("--passwords", &[Action::Revoke, Action::History]));
+            }
+            "--format" => {
+                format = parse_option(args.next(), "format")?;
+                restricted.push((
+                    "--format",
+                    &[Action::Get, Action::Revoke, Action::History, Action::Batch],
+                ));
+            }
+            "-0" | "--null" => {
+                null_separated = true;
+                restricted.push(("--null", &[Action::Batch]));
+            }
+            "--from-config" => {
+                from_config = true;
+                restricted.push(("--from-config", &[Action::Batch]));
+            }
+            // known flags
+            "--raw-domain" => raw_domain = true,
+            // known options
+            "-w" | "--words" => cli.words = Some(parse_amount_words(args.next())?),
+            "--wordlist" => cli.wordlist = Some(parse_option(args.next(), "word list path")?),
+            "-u" | "--user" => cli.user = Some(parse_option(args.next(), "user name")?),
+            "-l" | "--list" => cli.list = Some(parse_option(args.next(), "word list name")?),
+            // password policy
+            "--min-length" => cli.min_length = Some(parse_option(args.next(), "minimum length")?),
+            "--max-length" => cli.max_length = Some(parse_option(args.next(), "maximum length")?),
+            "--symbols" => cli.symbols = Some(parse_option(args.next(), "symbols")?),
+            "--digits" => cli.digits = Some(parse_option(args.next(), "number of digits")?),
+            "--separator" => cli.separator = Some(parse_option(args.next(), "separator")?),
+            "--case" => cli.case = Some(parse_option(args.next(), "capitalization")?),
+            // the rest is the domain, even if it begins with a dash
+            "--" => {
+                let rest: Vec<String> = args.by_ref().collect();
+                match rest.as_slice() {
+                    [] => {}
+                    [rest_domain] if domain.is_none() => domain = Some(rest_domain.clone()),
+                    _ => return Err(Error::Usage("Expecting only one domain".to_string())),
+                }
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(Error::Usage(format!(
+                    "Unknown option for {}: {}",
+                    action.name(),
+                    arg
+                )))
+            }
+            _ if domain.is_some() => {
+                return Err(Error::Usage("Expecting only one domain".to_string()))
+            }
+            _ => domain = Some(arg),
+        }
     }
 
-    // print final password
-    let words = words.join("");
-    format!("{words}_1")
+    for (option, actions) in restricted {
+        if !actions.contains(&action) {
+            let names: Vec<&str> = actions.iter().map(|a| a.name()).collect();
+            return Err(Error::Usage(format!(
+                "{} can only be used with {}",
+                option,
+                names.join(" or ")
+            )));
+        }
+    }
+
+    if format == Format::Env && matches!(action, Action::History | Action::Batch) {
+        return Err(Error::Usage(format!(
+            "--format env can't be used with {}, use json",
+            action.name()
+        )));
+    }
+
+    let config = get_config(raw_domain)?;
+
+    // the positional argument of batch is the file of domains
+    if action == Action::Batch {
+        let path = domain;
+        let domains = match (&path, from_config) {
+            (Some(_), true) => {
+                return Err(Error::Usage(
+                    "Expecting either a file of domains or --from-config".to_string(),
+                ))
+            }
+            (path, false) => read_batch_domains(path.as_deref(), null_separated)?,
+            (None, true) => config_domains(&config),
+        };
+        // with domains on stdin, the master password has to come from the terminal
+        let domains_on_stdin = !from_config && path_is_stdin(path.as_deref());
+        let master_password = read_master_password(domains_on_stdin || stdin().is_terminal())?;
+        return batch(
+            &domains,
+            &master_password,
+            &cli,
+            raw_domain,
+            &config,
+            format,
+            store,
+        );
+    }
+
+    // no domain argument = interactive mode
+    let domain = match domain {
+        Some(domain) => domain,
+        None => get_domain()?,
+    };
+    let (domain, settings, record) = resolve_domain(domain, raw_domain, &config, &cli)?;
+    print_wordlist(&settings);
+    match action {
+        Action::Get => {
+            let derived = match iteration {
+                None => xkcdget(domain, &settings, store)?,
+                Some(iteration) => xkcdget_iteration(domain, &settings, iteration, store)?,
+            };
+            print_entropy(&derived, &settings);
+            print_password(&derived, format, record);
+        }
+        Action::Revoke => {
+            let dry_run = revoke_options.dry_run;
+            let revocation = revoke(domain, &settings, revoke_options, show_passwords, store)?;
+            if let Some(next) = &revocation.next {
+                print_entropy(next, &settings);
+            }
+            print_revocation(&revocation, dry_run, format, record);
+        }
+        Action::Unrevoke => unrevoke(domain, &settings, store)?,
+        Action::History => {
+            let history = history(domain, &settings, show_passwords, store)?;
+            print_history(&history, format, record);
+        }
+        Action::Batch => unreachable!("Batch has been handled before"),
+    }
+    Ok(true)
 }
 
-/// Generate and revoke a password
-fn revoke(domain: String) {
-    let pw_scrypt = get_scrypt_z85(domain);
-    let pw_revocation_hash = get_revocation_hash(&pw_scrypt);
-    eprintln!("Revoking hash:{}", pw_revocation_hash);
+/// Run the command line, returning whether the command succeeded.
+///
+/// A command can run without error but fail, like a diff of differing files.
+fn run() -> Result<bool, Error> {
+    // options that apply to all commands, up to a "--"
+    let mut revocation_file = None;
+    let mut revocation_sources = Vec::new();
+    let mut accept_lost_entries = false;
+    let mut verbosity = Verbosity::Normal;
+    let mut other_args = Vec::new();
+    let mut all_args = args().skip(1);
+    while let Some(arg) = all_args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(true);
+            }
+            "-V" | "--version" => {
+                println!("xkcdget {XKCDGET_VERSION}");
+                return Ok(true);
+            }
+            "--revocation-file" => {
+                revocation_file = Some(parse_option(all_args.next(), "revocation file")?)
+            }
+            "--revocation-source" => {
+                revocation_sources.push(parse_option(all_args.next(), "revocation file")?)
+            }
+            "--accept-revocation-loss" => accept_lost_entries = true,
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "--" => {
+                other_args.push(arg);
+                other_args.extend(all_args.by_ref());
+            }
+            // the value belongs to the option, even if it is e.g. "-h"
+            _ if PASSWORD_VALUE_OPTIONS.contains(&arg.as_str()) => {
+                other_args.push(arg);
+                other_args.extend(all_args.next());
+            }
+            _ => other_args.push(arg),
+        }
+    }
 
-    // add hash to revocation file
-    let revocation_filename = get_revocation_filename();
-    let mut revocation_file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(revocation_filename)
-        .expect("Cannot create/open revocation file name for appending");
-    let _bytes_written = revocation_file
-        .write(format!("{pw_revocation_hash}\n").as_bytes())
-        .expect("Can't append revocation hash to revocation file");
-    revocation_file
-        .flush()
-        .expect("Can't flush revocation file handle");
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+    info!("xkcdget {XKCDGET_VERSION}");
+    // only built for the commands that use it, the others work without HOME
+    let store = || get_revocation_store(revocation_file, revocation_sources, accept_lost_entries);
+
+    // without a known command, the arguments are those of get
+    let command = other_args.first().cloned();
+    let command = command.as_deref();
+    let action = match command {
+        Some("get") => Some(Action::Get),
+        Some("revoke") => Some(Action::Revoke),
+        Some("unrevoke") => Some(Action::Unrevoke),
+        Some("history") => Some(Action::History),
+        Some("batch") => Some(Action::Batch),
+        _ => None,
+    };
+    if action.is_some() || matches!(command, Some("help" | "config" | "revocations")) {
+        other_args.remove(0);
+    }
+    match (command, action) {
+        (_, Some(action)) => password_command(action, other_args, &mut store()?),
+        (Some("help"), _) => {
+            print!("{}", USAGE);
+            Ok(true)
+        }
+        (Some("config"), _) => {
+            let mut args = other_args.into_iter();
+            match args.next().as_deref() {
+                Some("check") => {
+                    expect_no_arguments(&mut args, "check")?;
+                    config_check()
+                }
+                _ => Err(Error::Usage("Expecting config command: check".to_string())),
+            }
+        }
+        (Some("revocations"), _) => revocations_command(other_args, &mut store()?),
+        _ => password_command(Action::Get, other_args, &mut store()?),
+    }
 }
 
-/// Dispatch according to program arguments.
 fn main() {
-    eprintln!("xkcdget {XKCDGET_VERSION}");
-    print_entropy();
-    let mut args = args();
-    match args.nth(1) {
-        // no argument = interactive mode
-        None => println!("{}", xkcdget(get_domain())),
-
-        // the first argument is either an action flag or a domain
-        Some(arg) => match arg.as_str() {
-            // known action flags
-            "-r" | "--revoke" => revoke(args.next().unwrap_or_else(get_domain)),
-            // not a known action flag, so treat as a domain
-            _ => println!("{}", xkcdget(arg)),
-        },
+    match run() {
+        Ok(true) => {}
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            if let Error::Usage(_) = e {
+                eprintln!("Run xkcdget --help for usage.");
+            }
+            exit(e.exit_code());
+        }
     }
 }
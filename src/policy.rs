@@ -0,0 +1,159 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Digits appended after the symbol, in this order.
+const DIGITS: &str = "1234567890";
+
+/// Errors that can occur when applying a password policy.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The minimum length is bigger than the maximum length
+    MinAboveMax {
+        min_length: usize,
+        max_length: usize,
+    },
+    /// More digits than available
+    TooManyDigits { digits: usize },
+    /// No password with the word list can satisfy the length limits
+    Unsatisfiable,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MinAboveMax {
+                min_length,
+                max_length,
+            } => write!(
+                f,
+                "minimum length {} is bigger than maximum length {}",
+                min_length, max_length
+            ),
+            Self::TooManyDigits { digits } => {
+                write!(
+                    f,
+                    "{} digits requested, at most {} possible",
+                    digits,
+                    DIGITS.len()
+                )
+            }
+            Self::Unsatisfiable => write!(f, "no password can satisfy the length limits"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// How the words of a password are capitalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Capitalization {
+    /// First letter of each word upper-case, the rest lower-case
+    #[default]
+    Camel,
+    /// All letters lower-case
+    Lower,
+    /// All letters upper-case
+    Upper,
+}
+
+impl Capitalization {
+    /// Apply the capitalization to a word.
+    pub fn apply(self, word: &str) -> String {
+        match self {
+            Self::Camel => {
+                let mut word_chars = word.chars();
+                let first_char = word_chars.next().expect("Chosen word is empty");
+                let rest_chars = word_chars.flat_map(|c| c.to_lowercase());
+                first_char.to_uppercase().chain(rest_chars).collect()
+            }
+            Self::Lower => word.to_lowercase(),
+            Self::Upper => word.to_uppercase(),
+        }
+    }
+}
+
+impl FromStr for Capitalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "camel" => Ok(Self::Camel),
+            "lower" => Ok(Self::Lower),
+            "upper" => Ok(Self::Upper),
+            _ => Err(format!(
+                "unknown capitalization {}, expected camel, lower or upper",
+                s
+            )),
+        }
+    }
+}
+
+/// Rules a site imposes on passwords.
+///
+/// A password consists of the capitalized words joined by the separator,
+/// followed by a symbol and digits. The default policy yields the classic
+/// `CamelCaseWords_1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Policy {
+    /// Minimum number of characters, reached by adding words
+    pub min_length: usize,
+    /// Maximum number of characters, kept by choosing shorter or fewer words
+    pub max_length: Option<usize>,
+    /// Allowed symbols, the first one is used. Empty for no symbol.
+    pub symbols: String,
+    /// Number of digits after the symbol
+    pub digits: usize,
+    /// String between words
+    pub separator: String,
+    /// Capitalization of the words
+    pub capitalization: Capitalization,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_length: 0,
+            max_length: None,
+            symbols: "_".to_string(),
+            digits: 1,
+            separator: String::new(),
+            capitalization: Capitalization::Camel,
+        }
+    }
+}
+
+impl Policy {
+    /// Check that the policy is consistent in itself.
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.digits > DIGITS.len() {
+            return Err(PolicyError::TooManyDigits {
+                digits: self.digits,
+            });
+        }
+        match self.max_length {
+            Some(max_length) if max_length < self.min_length => Err(PolicyError::MinAboveMax {
+                min_length: self.min_length,
+                max_length,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Symbol and digits appended after the words.
+    pub fn suffix(&self) -> String {
+        let symbol = self.symbols.chars().next().map(String::from);
+        let digits = &DIGITS[..self.digits];
+        format!("{}{}", symbol.unwrap_or_default(), digits)
+    }
+
+    /// Number of characters that are not part of a word, for the given amount of words.
+    pub fn overhead(&self, amount_words: usize) -> usize {
+        let separators = self.separator.chars().count() * amount_words.saturating_sub(1);
+        separators + self.suffix().chars().count()
+    }
+
+    /// Join capitalized words to the final password.
+    pub fn assemble(&self, words: &[String]) -> String {
+        format!("{}{}", words.join(&self.separator), self.suffix())
+    }
+}
@@ -0,0 +1,1011 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::authentication::{MacChain, MAC_PREFIX};
+use crate::{AuthenticationKey, RevocationState, StateFile};
+
+/// Name of the revocation file inside the home directory, used if it exists.
+pub const REVOCATION_LIST_FILENAME: &str = ".xkcdget-revocation";
+
+/// Path of the revocation file inside the XDG data directory.
+pub const REVOCATION_DATA_FILENAME: &str = "xkcdget/revocation";
+
+/// Environment variable overriding the location of the revocation file.
+pub const REVOCATION_FILE_ENV: &str = "XKCDGET_REVOCATION_FILE";
+
+/// Environment variable with additional, read-only revocation files separated like `PATH`.
+pub const REVOCATION_SOURCES_ENV: &str = "XKCDGET_REVOCATION_SOURCES";
+
+/// Environment variable that makes authentication of the revocation file mandatory if `required`.
+pub const REVOCATION_AUTHENTICATION_ENV: &str = "XKCDGET_REVOCATION_AUTHENTICATION";
+
+/// Calculate the hash used for revocation.
+pub fn revocation_hash(key: &str) -> String {
+    let hash = hex::decode(sha256::digest(key)).expect("Cannot hex-decode sha256 digest");
+    z85::encode(hash)
+}
+
+/// Length of a decoded revocation hash, a SHA-256 digest.
+pub const REVOCATION_HASH_LEN: usize = 32;
+
+/// Decode a revocation hash, returning None if it isn't a z85-encoded SHA-256 digest.
+pub fn decode_revocation_hash(hash: &str) -> Option<[u8; REVOCATION_HASH_LEN]> {
+    z85::decode(hash).ok()?.try_into().ok()
+}
+
+/// Set of revocation hashes of passwords that must not be used anymore.
+///
+/// The hashes are kept as decoded digests in a hash set, so lookups stay fast
+/// with many revocations.
+#[derive(Clone, Debug, Default)]
+pub struct RevocationSet {
+    digests: HashSet<[u8; REVOCATION_HASH_LEN]>,
+}
+
+impl RevocationSet {
+    /// Create an empty revocation set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the given revocation hash has been revoked.
+    pub fn contains(&self, hash: &str) -> bool {
+        decode_revocation_hash(hash).is_some_and(|digest| self.digests.contains(&digest))
+    }
+
+    /// Add a revocation hash to the set.
+    ///
+    /// A string that isn't a revocation hash can't match any password and is ignored.
+    pub fn insert(&mut self, hash: &str) {
+        if let Some(digest) = decode_revocation_hash(hash) {
+            self.digests.insert(digest);
+        }
+    }
+
+    /// Number of revoked hashes.
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    /// Whether no hash has been revoked.
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Iterate over the revoked hashes, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        self.digests.iter().map(z85::encode)
+    }
+}
+
+impl FromIterator<String> for RevocationSet {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for hash in iter {
+            set.insert(&hash);
+        }
+        set
+    }
+}
+
+/// Why a password has been revoked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reason {
+    /// The password has been leaked
+    Compromised,
+    /// The password has been changed routinely
+    Rotation,
+    /// The password didn't fit the site's password policy
+    Policy,
+}
+
+impl Reason {
+    /// Name of the reason as used in the revocation file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Compromised => "compromised",
+            Self::Rotation => "rotation",
+            Self::Policy => "policy",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Reason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compromised" => Ok(Self::Compromised),
+            "rotation" => Ok(Self::Rotation),
+            "policy" => Ok(Self::Policy),
+            _ => Err(format!(
+                "unknown reason {}, expected compromised, rotation or policy",
+                s
+            )),
+        }
+    }
+}
+
+/// A revoked password hash with its metadata.
+///
+/// In the revocation file, an entry is a line `HASH TIME REASON [LABEL]`,
+/// where TIME is in seconds since the Unix epoch, REASON is `-` if unknown
+/// and LABEL is encrypted with a [`LabelKey`](crate::LabelKey). Lines of the
+/// old format only contain hashes and have no metadata.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RevocationEntry {
+    pub hash: String,
+    /// Seconds since the Unix epoch, unknown for entries of the old format
+    pub time: Option<u64>,
+    pub reason: Option<Reason>,
+    /// Encrypted label
+    pub label: Option<String>,
+}
+
+impl RevocationEntry {
+    /// Create an entry for a hash revoked now.
+    pub fn new(hash: String, reason: Option<Reason>, label: Option<String>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        Self {
+            hash,
+            time,
+            reason,
+            label,
+        }
+    }
+
+    /// Combine two entries of the same hash, keeping the newer time and all known metadata.
+    pub fn merge(&self, other: &Self) -> Self {
+        let (newer, older) = if other.time > self.time {
+            (other, self)
+        } else {
+            (self, other)
+        };
+        Self {
+            hash: newer.hash.clone(),
+            time: newer.time,
+            reason: newer.reason.or(older.reason),
+            label: newer.label.clone().or_else(|| older.label.clone()),
+        }
+    }
+
+    /// Format the entry as a line of the revocation file, without newline.
+    pub fn to_line(&self) -> String {
+        match self.time {
+            None => self.hash.clone(),
+            Some(time) => {
+                let reason = self.reason.map_or("-", Reason::as_str);
+                match &self.label {
+                    None => format!("{} {} {}", self.hash, time, reason),
+                    Some(label) => format!("{} {} {} {}", self.hash, time, reason, label),
+                }
+            }
+        }
+    }
+}
+
+/// Name in the first line of the revocation file, followed by the format version.
+const REVOCATION_FILE_HEADER: &str = "xkcdget-revocation";
+
+/// Version of the revocation file format that is written.
+pub const REVOCATION_FORMAT_VERSION: u32 = 2;
+
+/// Version of the revocation file format with MACs, which is written if authentication is enabled.
+///
+/// It extends version 2 with a last line `mac TAG`, where TAG is a MAC over
+/// the header line and all entries, chained line by line. There is no other
+/// MAC in the file, so removing the latest entries can't leave an older MAC
+/// at the end.
+pub const AUTHENTICATED_FORMAT_VERSION: u32 = 3;
+
+/// Error about the line with the given number.
+fn invalid_line(line: usize, message: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+/// Header line of an authenticated revocation file.
+fn authenticated_header() -> String {
+    format!(
+        "{} {}",
+        REVOCATION_FILE_HEADER, AUTHENTICATED_FORMAT_VERSION
+    )
+}
+
+/// Parser of a revocation file, fed line by line so that large files can be streamed.
+///
+/// With an authentication key, the MACs of an authenticated file are verified
+/// on the way.
+struct ListParser<'a> {
+    key: Option<&'a AuthenticationKey>,
+    line_number: usize,
+    /// Format version from the header, 1 for files without header
+    version: u32,
+    /// Whether a non-empty line has been seen
+    started: bool,
+    chain: Option<MacChain>,
+    /// Whether the last line has been a valid MAC
+    authenticated: bool,
+    /// Line of the MAC, after which no line may follow
+    mac_line: Option<usize>,
+    entries: Vec<RevocationEntry>,
+}
+
+impl<'a> ListParser<'a> {
+    fn new(key: Option<&'a AuthenticationKey>) -> Self {
+        Self {
+            key,
+            line_number: 0,
+            version: 1,
+            started: false,
+            chain: None,
+            authenticated: false,
+            mac_line: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Parse the next line.
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        self.line_number += 1;
+        let i = self.line_number;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        if let Some(mac_line) = self.mac_line {
+            return Err(invalid_line(
+                i,
+                format!(
+                    "line after the MAC in line {}, the MAC has to be the last line",
+                    mac_line
+                ),
+            ));
+        }
+        let first = !self.started;
+        self.started = true;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [REVOCATION_FILE_HEADER, version] => {
+                if !first {
+                    return Err(invalid_line(i, "header after the first line"));
+                }
+                let version: u32 = version
+                    .parse()
+                    .map_err(|_| invalid_line(i, format!("invalid version {}", version)))?;
+                if version > AUTHENTICATED_FORMAT_VERSION {
+                    return Err(invalid_line(
+                        i,
+                        format!("unsupported format version {}", version),
+                    ));
+                }
+                self.version = version;
+                if version == AUTHENTICATED_FORMAT_VERSION {
+                    self.chain = self.key.map(|key| MacChain::new(key, line));
+                }
+            }
+            [MAC_PREFIX, tag] if self.version >= AUTHENTICATED_FORMAT_VERSION => {
+                self.mac_line = Some(i);
+                if let Some(chain) = &self.chain {
+                    if *tag != chain.tag() {
+                        return Err(invalid_line(
+                            i,
+                            "authentication failed, entries have been removed or modified",
+                        ));
+                    }
+                    self.authenticated = true;
+                }
+            }
+            tokens => {
+                if let Some(chain) = &mut self.chain {
+                    chain.update(line);
+                    self.authenticated = false;
+                }
+                self.entry(tokens)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the tokens of an entry line.
+    fn entry(&mut self, tokens: &[&str]) -> io::Result<()> {
+        let i = self.line_number;
+        let valid_hash = |hash: &str| match decode_revocation_hash(hash) {
+            Some(_) => Ok(hash.to_string()),
+            None => Err(invalid_line(i, format!("invalid hash {}", hash))),
+        };
+        match tokens {
+            // entry with metadata
+            [hash, time, reason, label @ ..]
+                if self.version >= 2 || decode_revocation_hash(time).is_none() =>
+            {
+                let time = time
+                    .parse()
+                    .map_err(|_| invalid_line(i, format!("invalid time {}", time)))?;
+                let reason = match *reason {
+                    "-" => None,
+                    reason => Some(reason.parse().map_err(|e: String| invalid_line(i, e))?),
+                };
+                let label = match label {
+                    [] => None,
+                    [label] => Some(label.to_string()),
+                    _ => return Err(invalid_line(i, "too many fields")),
+                };
+                self.entries.push(RevocationEntry {
+                    hash: valid_hash(hash)?,
+                    time: Some(time),
+                    reason,
+                    label,
+                });
+            }
+            // entry without metadata
+            [hash] => self.entries.push(RevocationEntry {
+                hash: valid_hash(hash)?,
+                time: None,
+                reason: None,
+                label: None,
+            }),
+            // old format: whitespace-separated hashes
+            hashes if self.version == 1 => {
+                for hash in hashes {
+                    self.entries.push(RevocationEntry {
+                        hash: valid_hash(hash)?,
+                        time: None,
+                        reason: None,
+                        label: None,
+                    });
+                }
+            }
+            _ => return Err(invalid_line(i, "expected HASH TIME REASON [LABEL]")),
+        }
+        Ok(())
+    }
+
+    /// Whether the header is that of an authenticated file.
+    fn is_authenticated_list(&self) -> bool {
+        self.version == AUTHENTICATED_FORMAT_VERSION
+    }
+
+    /// Finish parsing, returning the entries and the MAC chain of a verified file.
+    fn finish(self) -> io::Result<(Vec<RevocationEntry>, Option<MacChain>)> {
+        if self.chain.is_some() && !self.authenticated {
+            return Err(invalid_line(
+                self.line_number,
+                "authentication missing, the file has been truncated or entries have been added",
+            ));
+        }
+        Ok((self.entries, self.chain))
+    }
+}
+
+/// Read a revocation file of any format version line by line.
+///
+/// Malformed lines are rejected with their line number. MACs of authenticated
+/// files are skipped, they are verified by [`FileRevocationStore`] if it has
+/// an authentication key.
+pub fn read_revocation_list(reader: impl BufRead) -> io::Result<Vec<RevocationEntry>> {
+    let mut parser = ListParser::new(None);
+    for line in reader.lines() {
+        parser.line(&line?)?;
+    }
+    Ok(parser.finish()?.0)
+}
+
+/// Parse the content of a revocation file of any format version.
+pub fn parse_revocation_list(content: &str) -> io::Result<Vec<RevocationEntry>> {
+    read_revocation_list(content.as_bytes())
+}
+
+/// Format entries as the content of a revocation file of the current format version.
+pub fn format_revocation_list(entries: &[RevocationEntry]) -> String {
+    let mut content = format!("{} {}\n", REVOCATION_FILE_HEADER, REVOCATION_FORMAT_VERSION);
+    for entry in entries {
+        content.push_str(&entry.to_line());
+        content.push('\n');
+    }
+    content
+}
+
+/// Union of two lists of entries, with one entry per hash.
+///
+/// Entries of the same hash are combined with [`RevocationEntry::merge`].
+/// The order of the first list is kept, new hashes are appended.
+pub fn merge_revocation_lists(
+    entries: &[RevocationEntry],
+    other: &[RevocationEntry],
+) -> Vec<RevocationEntry> {
+    let mut merged: Vec<RevocationEntry> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for entry in entries.iter().chain(other) {
+        match positions.get(&entry.hash) {
+            Some(&i) => merged[i] = merged[i].merge(entry),
+            None => {
+                positions.insert(entry.hash.clone(), merged.len());
+                merged.push(entry.clone());
+            }
+        }
+    }
+    merged
+}
+
+/// Entries whose hash is only in the left list and entries whose hash is only in the right list.
+pub fn diff_revocation_lists<'a>(
+    left: &'a [RevocationEntry],
+    right: &'a [RevocationEntry],
+) -> (Vec<&'a RevocationEntry>, Vec<&'a RevocationEntry>) {
+    let left_hashes: HashSet<&str> = left.iter().map(|e| e.hash.as_str()).collect();
+    let right_hashes: HashSet<&str> = right.iter().map(|e| e.hash.as_str()).collect();
+    let only_left = left
+        .iter()
+        .filter(|e| !right_hashes.contains(e.hash.as_str()))
+        .collect();
+    let only_right = right
+        .iter()
+        .filter(|e| !left_hashes.contains(e.hash.as_str()))
+        .collect();
+    (only_left, only_right)
+}
+
+/// Persistent storage for revocation hashes.
+pub trait RevocationStore {
+    /// Read all revocation entries.
+    fn entries(&self) -> io::Result<Vec<RevocationEntry>>;
+
+    /// Read all hashes that have been revoked.
+    fn load(&self) -> io::Result<RevocationSet> {
+        Ok(self.entries()?.into_iter().map(|e| e.hash).collect())
+    }
+
+    /// Persistently add a revocation entry.
+    fn revoke(&mut self, entry: RevocationEntry) -> io::Result<()>;
+
+    /// Persistently remove all entries of a hash, returning how many have been removed.
+    fn unrevoke(&mut self, hash: &str) -> io::Result<usize>;
+
+    /// Persistently merge entries from elsewhere, returning how many entries have been added or updated.
+    fn merge(&mut self, other: &[RevocationEntry]) -> io::Result<usize>;
+}
+
+/// Number of entries of the merged list that differ from the original list.
+fn count_changes(entries: &[RevocationEntry], merged: &[RevocationEntry]) -> usize {
+    let entries: HashSet<&RevocationEntry> = entries.iter().collect();
+    merged.iter().filter(|e| !entries.contains(e)).count()
+}
+
+/// Revocation store backed by a file with one entry per line.
+///
+/// With an authentication key, authenticated files are verified on every read
+/// and their MACs are kept up to date on every write.
+#[derive(Clone, Debug)]
+pub struct FileRevocationStore {
+    path: PathBuf,
+    authentication_key: Option<AuthenticationKey>,
+    require_authentication: bool,
+    state_file: Option<StateFile>,
+    accept_lost_entries: bool,
+}
+
+impl FileRevocationStore {
+    /// Use the revocation file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            authentication_key: None,
+            require_authentication: false,
+            state_file: None,
+            accept_lost_entries: false,
+        }
+    }
+
+    /// Compare the file on every read to the state recorded in the state file at the last run.
+    ///
+    /// Reading fails if the file is missing, has lost entries or isn't
+    /// authenticated anymore, unless that is accepted.
+    pub fn set_state_file(&mut self, state_file: StateFile) {
+        self.state_file = Some(state_file);
+    }
+
+    /// Accept a missing file, lost entries or lost authentication once, and record the new state.
+    pub fn set_accept_lost_entries(&mut self, accept: bool) {
+        self.accept_lost_entries = accept;
+    }
+
+    /// Set the key to verify and update the MACs of an authenticated file.
+    pub fn set_authentication_key(&mut self, key: AuthenticationKey) {
+        self.authentication_key = Some(key);
+    }
+
+    /// Reject a file that isn't authenticated, and create new files authenticated.
+    pub fn set_require_authentication(&mut self, require: bool) {
+        self.require_authentication = require;
+    }
+
+    /// Whether the file is authenticated or has to be, so that it needs an authentication key.
+    pub fn needs_authentication_key(&self) -> io::Result<bool> {
+        if self.require_authentication {
+            return Ok(true);
+        }
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        // the header is the first non-empty line
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                return Ok(line.trim() == authenticated_header());
+            }
+        }
+        Ok(false)
+    }
+
+    /// Convert the file to an authenticated file.
+    pub fn authenticate(&mut self) -> io::Result<()> {
+        let lock = self.open_lock()?;
+        lock.lock()?;
+        let (entries, _) = self.read_entries()?;
+        self.rewrite(&entries, true)
+    }
+
+    /// Default location of the revocation file.
+    ///
+    /// This is `$XKCDGET_REVOCATION_FILE` if set. Else the file recorded in the
+    /// state file is kept, `~/.xkcdget-revocation` before the file in the XDG
+    /// data directory. Without a record, `~/.xkcdget-revocation` is used if it
+    /// exists, else the XDG file. The state file keeps either file from being
+    /// replaced by the other one unnoticed, e.g. when an old version of
+    /// xkcdget creates `~/.xkcdget-revocation` next to a used XDG file.
+    pub fn default_path() -> Option<PathBuf> {
+        match std::env::var_os(REVOCATION_FILE_ENV) {
+            Some(path) if !path.is_empty() => return Some(PathBuf::from(path)),
+            _ => {}
+        }
+        let home = std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from);
+        let legacy = home
+            .as_ref()
+            .map(|home| home.join(REVOCATION_LIST_FILENAME));
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => home.map(|home| home.join(".local/share")),
+        };
+        let xdg = data_home.map(|dir| dir.join(REVOCATION_DATA_FILENAME));
+
+        let state_file = StateFile::default_path().map(StateFile::new);
+        let recorded = |path: &Option<PathBuf>| match (path, &state_file) {
+            (Some(path), Some(state_file)) => state_file.get(path).ok().flatten().is_some(),
+            _ => false,
+        };
+        if recorded(&legacy) {
+            return legacy;
+        }
+        if recorded(&xdg) {
+            return xdg;
+        }
+        match legacy {
+            Some(legacy) if legacy.exists() => Some(legacy),
+            _ => xdg,
+        }
+    }
+
+    /// Path of the revocation file.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Path next to the revocation file with the given extension appended.
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    }
+
+    /// Open the lock file that guards the revocation file.
+    ///
+    /// A separate lock file is used because rewriting replaces the revocation
+    /// file, and a lock on the replaced file wouldn't guard the new one.
+    /// The lock is released when the returned file is dropped.
+    fn open_lock(&self) -> io::Result<File> {
+        let lock_path = self.sibling(".lock");
+        let with_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", lock_path.display(), e));
+        // the XDG data directory may not exist yet
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent).map_err(with_path)?;
+        }
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(with_path)
+    }
+
+    /// The authentication key, or an error mentioning the file if there is none.
+    fn authentication_key(&self) -> io::Result<&AuthenticationKey> {
+        self.authentication_key.as_ref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{} is authenticated, the master password is needed to verify it",
+                    self.path.display()
+                ),
+            )
+        })
+    }
+
+    /// Read and verify the entries without locking.
+    ///
+    /// For authenticated files, the MAC chain at the end of the file is returned as well.
+    fn read_entries(&self) -> io::Result<(Vec<RevocationEntry>, Option<MacChain>)> {
+        let with_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e));
+        let mut parser = ListParser::new(self.authentication_key.as_ref());
+        match File::open(&self.path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    parser.line(&line.map_err(with_path)?).map_err(with_path)?;
+                    // an authenticated file can't be read without verifying it
+                    if parser.is_authenticated_list() {
+                        self.authentication_key()?;
+                    }
+                }
+            }
+            // treat nonexisting file like an empty file
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(with_path(e)),
+        }
+        if self.require_authentication && parser.started && !parser.is_authenticated_list() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} isn't authenticated", self.path.display()),
+            ));
+        }
+        let (entries, chain) = parser.finish().map_err(with_path)?;
+        self.check_state(&entries, chain.is_some())?;
+        Ok((entries, chain))
+    }
+
+    /// Compare the entries to the recorded state and record their state.
+    ///
+    /// A file that has been authenticated has to stay authenticated, otherwise
+    /// anyone who can write it could replace it by a file without MACs.
+    fn check_state(&self, entries: &[RevocationEntry], authenticated: bool) -> io::Result<()> {
+        let Some(state_file) = &self.state_file else {
+            return Ok(());
+        };
+        let state = RevocationState::of(entries, authenticated);
+        let earlier = state_file.get(&self.path)?;
+        if let Some(earlier) = &earlier {
+            let lost_authentication = earlier.authenticated && !state.authenticated;
+            if (state.lost_entries_since(earlier) || lost_authentication)
+                && !self.accept_lost_entries
+            {
+                let problem = if !self.path.exists() {
+                    "is missing".to_string()
+                } else if state.lost_entries_since(earlier) {
+                    format!("has lost entries, it has {} revoked hashes", state.count)
+                } else {
+                    "isn't authenticated anymore".to_string()
+                };
+                let authenticated = if earlier.authenticated {
+                    " and was authenticated"
+                } else {
+                    ""
+                };
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} {}, but had {}{} at the last run (recorded in {})",
+                        self.path.display(),
+                        problem,
+                        earlier.count,
+                        authenticated,
+                        state_file.path().display()
+                    ),
+                ));
+            }
+        }
+        if earlier.as_ref() != Some(&state) {
+            state_file.set(&self.path, &state)?;
+        }
+        Ok(())
+    }
+
+    /// Record the state after writing the entries.
+    fn record_state(&self, entries: &[RevocationEntry], authenticated: bool) -> io::Result<()> {
+        match &self.state_file {
+            Some(state_file) => {
+                state_file.set(&self.path, &RevocationState::of(entries, authenticated))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a file with the given MAC chain is written authenticated.
+    fn write_authenticated(&self, chain: &Option<MacChain>) -> bool {
+        chain.is_some() || self.require_authentication
+    }
+
+    /// Replace the content of the revocation file, the caller must hold the lock.
+    ///
+    /// The entries are written to a temporary file next to the revocation file,
+    /// which is synced to disk and then renamed, so that the file is never left
+    /// half-written.
+    fn rewrite(&self, entries: &[RevocationEntry], authenticated: bool) -> io::Result<()> {
+        let content = if authenticated {
+            let header = authenticated_header();
+            let mut chain = MacChain::new(self.authentication_key()?, &header);
+            let mut content = format!("{}\n", header);
+            for entry in entries {
+                let line = entry.to_line();
+                chain.update(&line);
+                content.push_str(&line);
+                content.push('\n');
+            }
+            content.push_str(&chain.line());
+            content.push('\n');
+            content
+        } else {
+            format_revocation_list(entries)
+        };
+        let tmp_path = self.sibling(".tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(content.as_bytes())?;
+        tmp_file.sync_all()?;
+        drop(tmp_file);
+        rename(&tmp_path, &self.path)?;
+        sync_parent_dir(&self.path)?;
+        self.record_state(entries, authenticated)
+    }
+}
+
+/// Sync the directory containing the path, so that a rename is persisted.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories can't be synced on this platform, the rename is persisted by the file system.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+impl RevocationStore for FileRevocationStore {
+    fn entries(&self) -> io::Result<Vec<RevocationEntry>> {
+        // without a revocation file there is nothing to lock
+        if !self.path.exists() {
+            self.check_state(&[], false)?;
+            return Ok(Vec::new());
+        }
+        // a file in a directory that can't be written, like a team file, is
+        // read without lock, it isn't written by this user either
+        let lock = match self.open_lock() {
+            Ok(lock) => Some(lock),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                ) =>
+            {
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(lock) = &lock {
+            lock.lock_shared()?;
+        }
+        Ok(self.read_entries()?.0)
+    }
+
+    fn revoke(&mut self, entry: RevocationEntry) -> io::Result<()> {
+        let lock = self.open_lock()?;
+        lock.lock()?;
+        let (mut entries, chain) = self.read_entries()?;
+        let empty = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len() == 0,
+            Err(e) if e.kind() == ErrorKind::NotFound => true,
+            Err(e) => return Err(e),
+        };
+        if chain.is_some() || (empty && self.require_authentication) {
+            // an authenticated file ends with its only MAC, which has to be replaced
+            entries.push(entry);
+            return self.rewrite(&entries, true);
+        }
+        let mut revocation_file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        let mut content = String::new();
+        // a new file starts with the format version, old files stay readable without it
+        if empty {
+            content = format!("{} {}\n", REVOCATION_FILE_HEADER, REVOCATION_FORMAT_VERSION);
+        }
+        content.push_str(&entry.to_line());
+        content.push('\n');
+        // one write of the whole line, so concurrent readers never see half a line
+        revocation_file.write_all(content.as_bytes())?;
+        revocation_file.sync_all()?;
+        entries.push(entry);
+        self.record_state(&entries, false)
+    }
+
+    fn unrevoke(&mut self, hash: &str) -> io::Result<usize> {
+        let lock = self.open_lock()?;
+        lock.lock()?;
+        let (mut entries, chain) = self.read_entries()?;
+        let before = entries.len();
+        entries.retain(|e| e.hash != hash);
+        let removed = before - entries.len();
+        if removed > 0 {
+            self.rewrite(&entries, self.write_authenticated(&chain))?;
+        }
+        Ok(removed)
+    }
+
+    fn merge(&mut self, other: &[RevocationEntry]) -> io::Result<usize> {
+        let lock = self.open_lock()?;
+        lock.lock()?;
+        let (entries, chain) = self.read_entries()?;
+        let merged = merge_revocation_lists(&entries, other);
+        // also rewrite if only duplicates have been removed
+        if merged != entries {
+            self.rewrite(&merged, self.write_authenticated(&chain))?;
+        }
+        Ok(count_changes(&entries, &merged))
+    }
+}
+
+/// Revocation store that reads several files and writes to one of them.
+///
+/// This allows consulting e.g. a read-only team file in addition to the
+/// personal revocation file. Revocations are only added to and removed from
+/// the writable file.
+#[derive(Clone, Debug)]
+pub struct LayeredRevocationStore {
+    writable: FileRevocationStore,
+    read_only: Vec<FileRevocationStore>,
+}
+
+impl LayeredRevocationStore {
+    /// Use the writable revocation file and additional read-only ones.
+    pub fn new(writable: FileRevocationStore, read_only: Vec<FileRevocationStore>) -> Self {
+        Self {
+            writable,
+            read_only,
+        }
+    }
+
+    /// Read-only revocation files from `$XKCDGET_REVOCATION_SOURCES`.
+    pub fn sources_from_env() -> Vec<FileRevocationStore> {
+        std::env::var_os(REVOCATION_SOURCES_ENV)
+            .map(|paths| {
+                std::env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .map(FileRevocationStore::new)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The file revocations are written to.
+    pub fn writable(&self) -> &FileRevocationStore {
+        &self.writable
+    }
+
+    /// The files that are only read.
+    pub fn read_only(&self) -> &[FileRevocationStore] {
+        &self.read_only
+    }
+
+    /// The file revocations are written to, for changing its settings.
+    pub fn writable_mut(&mut self) -> &mut FileRevocationStore {
+        &mut self.writable
+    }
+
+    /// Set the authentication key of all files.
+    pub fn set_authentication_key(&mut self, key: AuthenticationKey) {
+        for store in self.read_only.iter_mut() {
+            store.set_authentication_key(key.clone());
+        }
+        self.writable.set_authentication_key(key);
+    }
+
+    /// Whether any file is authenticated or has to be, so that it needs an authentication key.
+    pub fn needs_authentication_key(&self) -> io::Result<bool> {
+        for store in std::iter::once(&self.writable).chain(&self.read_only) {
+            if store.needs_authentication_key()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl RevocationStore for LayeredRevocationStore {
+    fn entries(&self) -> io::Result<Vec<RevocationEntry>> {
+        let mut entries = self.writable.entries()?;
+        for source in &self.read_only {
+            // a missing source would silently bring back revoked passwords
+            if !source.path().exists() {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} doesn't exist", source.path().display()),
+                ));
+            }
+            entries = merge_revocation_lists(&entries, &source.entries()?);
+        }
+        Ok(entries)
+    }
+
+    fn revoke(&mut self, entry: RevocationEntry) -> io::Result<()> {
+        self.writable.revoke(entry)
+    }
+
+    fn unrevoke(&mut self, hash: &str) -> io::Result<usize> {
+        self.writable.unrevoke(hash)
+    }
+
+    fn merge(&mut self, other: &[RevocationEntry]) -> io::Result<usize> {
+        self.writable.merge(other)
+    }
+}
+
+/// Revocation store that only lives in memory, e.g. for tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryRevocationStore {
+    entries: Vec<RevocationEntry>,
+}
+
+impl MemoryRevocationStore {
+    /// Create an empty in-memory store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn entries(&self) -> io::Result<Vec<RevocationEntry>> {
+        Ok(self.entries.clone())
+    }
+
+    fn revoke(&mut self, entry: RevocationEntry) -> io::Result<()> {
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn unrevoke(&mut self, hash: &str) -> io::Result<usize> {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.hash != hash);
+        Ok(before - self.entries.len())
+    }
+
+    fn merge(&mut self, other: &[RevocationEntry]) -> io::Result<usize> {
+        let merged = merge_revocation_lists(&self.entries, other);
+        let changes = count_changes(&self.entries, &merged);
+        self.entries = merged;
+        Ok(changes)
+    }
+}
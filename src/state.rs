@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+use std::fs::{create_dir_all, read_to_string, rename, write};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::RevocationEntry;
+
+/// Path of the state file inside the XDG state directory.
+pub const STATE_FILENAME: &str = "xkcdget/revocation-state";
+
+/// Flag of the records of authenticated revocation files.
+const AUTHENTICATED_FLAG: &str = "authenticated";
+
+/// Number and digest of the revoked hashes of a revocation file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RevocationState {
+    /// Number of distinct revoked hashes
+    pub count: usize,
+    /// SHA-256 of the sorted distinct hashes, one per line
+    pub digest: String,
+    /// Whether the file has been authenticated with MACs
+    pub authenticated: bool,
+}
+
+impl RevocationState {
+    /// Calculate the state of the entries of a revocation file.
+    pub fn of(entries: &[RevocationEntry], authenticated: bool) -> Self {
+        let hashes: BTreeSet<&str> = entries.iter().map(|e| e.hash.as_str()).collect();
+        let hashes: Vec<&str> = hashes.into_iter().collect();
+        Self {
+            count: hashes.len(),
+            digest: sha256::digest(hashes.join("\n")),
+            authenticated,
+        }
+    }
+
+    /// Whether hashes may have been lost since the earlier state.
+    ///
+    /// Added hashes are fine, but fewer hashes or the same number of different
+    /// hashes mean that some have been removed.
+    pub fn lost_entries_since(&self, earlier: &Self) -> bool {
+        self.count < earlier.count || (self.count == earlier.count && self.digest != earlier.digest)
+    }
+}
+
+/// File recording the state of revocation files at the last run.
+///
+/// It is kept apart from the revocation files, so that a lost or truncated
+/// revocation file is noticed, and so is an authenticated file that has been
+/// replaced by one without MACs. Each line is `COUNT DIGEST PATH`, or
+/// `COUNT DIGEST authenticated PATH` for authenticated files. Paths are
+/// absolute, so they can't be confused with the flag.
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    /// Use the state file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Default location of the state file, following the XDG base directory spec.
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+        };
+        Some(state_home.join(STATE_FILENAME))
+    }
+
+    /// Path of the state file.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Error about the state file, mentioning its path.
+    fn error(&self, e: io::Error) -> io::Error {
+        io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e))
+    }
+
+    /// Read all records, a missing file has none.
+    fn records(&self) -> io::Result<Vec<(PathBuf, RevocationState)>> {
+        let content = match read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(self.error(e)),
+        };
+        let mut records = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: line {}: invalid record", self.path.display(), i + 1),
+                )
+            };
+            let mut fields = line.splitn(3, ' ');
+            let count = fields.next().and_then(|c| c.parse().ok());
+            let (Some(count), Some(digest), Some(path)) = (count, fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let flagged = path
+                .strip_prefix(AUTHENTICATED_FLAG)
+                .and_then(|path| path.strip_prefix(' '));
+            let state = RevocationState {
+                count,
+                digest: digest.to_string(),
+                authenticated: flagged.is_some(),
+            };
+            let path = flagged.unwrap_or(path);
+            records.push((PathBuf::from(path), state));
+        }
+        Ok(records)
+    }
+
+    /// State of a revocation file at the last run, if it has been recorded.
+    pub fn get(&self, revocation_file: &Path) -> io::Result<Option<RevocationState>> {
+        let revocation_file = std::path::absolute(revocation_file)?;
+        Ok(self
+            .records()?
+            .into_iter()
+            .find(|(path, _)| *path == revocation_file)
+            .map(|(_, state)| state))
+    }
+
+    /// Record the state of a revocation file.
+    pub fn set(&self, revocation_file: &Path, state: &RevocationState) -> io::Result<()> {
+        let revocation_file = std::path::absolute(revocation_file)?;
+        let mut records = self.records()?;
+        records.retain(|(path, _)| *path != revocation_file);
+        records.push((revocation_file, state.clone()));
+        let content: String = records
+            .iter()
+            .map(|(path, state)| {
+                let flag = if state.authenticated {
+                    format!("{} ", AUTHENTICATED_FLAG)
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{} {} {}{}\n",
+                    state.count,
+                    state.digest,
+                    flag,
+                    path.display()
+                )
+            })
+            .collect();
+
+        // replace the file at once, concurrent runs only race for the latest state
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent).map_err(|e| self.error(e))?;
+        }
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        write(&tmp_path, content)
+            .and_then(|_| rename(&tmp_path, &self.path))
+            .map_err(|e| self.error(e))
+    }
+}
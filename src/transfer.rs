@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::{decode_revocation_hash, RevocationEntry, REVOCATION_HASH_LEN};
+
+/// Prefix of a transfer string, including the version of its format.
+pub const TRANSFER_PREFIX: &str = "xkcdget-revocations-1:";
+
+/// Length of the checksum at the end of the payload.
+const CHECKSUM_LEN: usize = 4;
+
+/// Number of hashes per transfer string.
+///
+/// Each hash takes 40 characters, so a string stays below 850 characters and
+/// its QR code small enough to be shown in a terminal and scanned reliably.
+pub const TRANSFER_CHUNK_HASHES: usize = 20;
+
+/// Errors that can occur when exporting or importing revocations.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransferError {
+    /// A hash of the revocation list isn't a z85-encoded SHA-256 digest
+    InvalidHash(String),
+    /// The string doesn't start with the expected prefix
+    InvalidPrefix,
+    /// The string has no valid `INDEX/COUNT:ID:` header
+    InvalidHeader,
+    /// The payload isn't valid z85 or has a wrong length
+    InvalidEncoding,
+    /// The checksum doesn't match, the string has been altered
+    ChecksumMismatch,
+    /// The strings belong to different exports
+    MixedExports,
+    /// Strings of the export are missing, given as indices and count
+    MissingStrings(Vec<usize>, usize),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHash(hash) => write!(f, "invalid revocation hash {}", hash),
+            Self::InvalidPrefix => write!(f, "expected a string starting with {}", TRANSFER_PREFIX),
+            Self::InvalidHeader => write!(f, "expected INDEX/COUNT:ID: after the prefix"),
+            Self::InvalidEncoding => write!(f, "invalid encoding"),
+            Self::ChecksumMismatch => {
+                write!(f, "checksum mismatch, the string is incomplete or altered")
+            }
+            Self::MixedExports => write!(f, "the strings belong to different exports"),
+            Self::MissingStrings(missing, count) => {
+                let missing: Vec<String> = missing.iter().map(|i| i.to_string()).collect();
+                write!(f, "missing string {} of {}", missing.join(", "), count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+/// First bytes of the SHA-256 digest of the data.
+fn checksum(data: &[u8]) -> Vec<u8> {
+    let digest = hex::decode(sha256::digest(data)).expect("Cannot hex-decode sha256 digest");
+    digest[..CHECKSUM_LEN].to_vec()
+}
+
+/// Header of a transfer string, covered by the checksum of its payload.
+fn header(index: usize, count: usize, id: &str) -> String {
+    format!("{}/{}:{}:", index, count, id)
+}
+
+/// Encode the revoked hashes as compact, checksummed strings for copying to another device.
+///
+/// Only the hashes are transferred, without time, reason and label. Each
+/// string holds up to [`TRANSFER_CHUNK_HASHES`] hashes and consists of the
+/// prefix, a header `INDEX/COUNT:ID:` and the z85 encoding of the
+/// concatenated digests and a checksum over header and digests. The ID is a
+/// checksum of all digests, so strings of different exports can't be mixed.
+pub fn export_revocations(entries: &[RevocationEntry]) -> Result<Vec<String>, TransferError> {
+    let mut seen = HashSet::new();
+    let mut hashes = Vec::new();
+    for entry in entries {
+        if !seen.insert(entry.hash.as_str()) {
+            continue;
+        }
+        match decode_revocation_hash(&entry.hash) {
+            Some(hash) => hashes.extend(hash),
+            None => return Err(TransferError::InvalidHash(entry.hash.clone())),
+        }
+    }
+    let id = hex::encode(checksum(&hashes));
+    // an empty list is still exported as one string, so it can be imported
+    let chunks: Vec<&[u8]> = if hashes.is_empty() {
+        vec![&[]]
+    } else {
+        hashes
+            .chunks(TRANSFER_CHUNK_HASHES * REVOCATION_HASH_LEN)
+            .collect()
+    };
+    let count = chunks.len();
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let header = header(i + 1, count, &id);
+            let mut payload = chunk.to_vec();
+            payload.extend(checksum(&[header.as_bytes(), chunk].concat()));
+            format!("{}{}{}", TRANSFER_PREFIX, header, z85::encode(payload))
+        })
+        .collect())
+}
+
+/// Decode one string of [`export_revocations`] into index, count, ID and digests.
+fn import_chunk(transfer: &str) -> Result<(usize, usize, String, Vec<u8>), TransferError> {
+    let rest = transfer
+        .strip_prefix(TRANSFER_PREFIX)
+        .ok_or(TransferError::InvalidPrefix)?;
+    let (position, rest) = rest.split_once(':').ok_or(TransferError::InvalidHeader)?;
+    let (id, encoded) = rest.split_once(':').ok_or(TransferError::InvalidHeader)?;
+    let (index, count) = position
+        .split_once('/')
+        .and_then(|(index, count)| Some((index.parse().ok()?, count.parse().ok()?)))
+        .filter(|&(index, count): &(usize, usize)| index >= 1 && index <= count)
+        .ok_or(TransferError::InvalidHeader)?;
+    let payload = z85::decode(encoded).map_err(|_| TransferError::InvalidEncoding)?;
+    if payload.len() < CHECKSUM_LEN
+        || !(payload.len() - CHECKSUM_LEN).is_multiple_of(REVOCATION_HASH_LEN)
+    {
+        return Err(TransferError::InvalidEncoding);
+    }
+    let (hashes, expected) = payload.split_at(payload.len() - CHECKSUM_LEN);
+    if checksum(&[header(index, count, id).as_bytes(), hashes].concat()) != expected {
+        return Err(TransferError::ChecksumMismatch);
+    }
+    Ok((index, count, id.to_string(), hashes.to_vec()))
+}
+
+/// Reassemble the strings of [`export_revocations`] into entries without metadata.
+///
+/// The strings may be given in any order, also several of them separated by
+/// whitespace in one string. Fails with [`TransferError::MissingStrings`]
+/// until all strings of the export are given.
+pub fn import_revocations<S: AsRef<str>>(
+    transfers: &[S],
+) -> Result<Vec<RevocationEntry>, TransferError> {
+    let mut chunks: Vec<Option<Vec<u8>>> = Vec::new();
+    let mut export_id: Option<String> = None;
+    for transfer in transfers
+        .iter()
+        .flat_map(|transfer| transfer.as_ref().split_whitespace())
+    {
+        let (index, count, id, hashes) = import_chunk(transfer)?;
+        if export_id.get_or_insert_with(|| id.clone()) != &id {
+            return Err(TransferError::MixedExports);
+        }
+        if chunks.is_empty() {
+            chunks.resize(count, None);
+        } else if chunks.len() != count {
+            return Err(TransferError::MixedExports);
+        }
+        chunks[index - 1] = Some(hashes);
+    }
+    if chunks.is_empty() {
+        return Err(TransferError::InvalidPrefix);
+    }
+    let missing: Vec<usize> = (1..=chunks.len())
+        .filter(|&i| chunks[i - 1].is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(TransferError::MissingStrings(missing, chunks.len()));
+    }
+    let hashes: Vec<u8> = chunks.into_iter().flatten().flatten().collect();
+    if export_id.as_deref() != Some(hex::encode(checksum(&hashes)).as_str()) {
+        return Err(TransferError::MixedExports);
+    }
+    Ok(hashes
+        .chunks(REVOCATION_HASH_LEN)
+        .map(|hash| RevocationEntry {
+            hash: z85::encode(hash),
+            time: None,
+            reason: None,
+            label: None,
+        })
+        .collect())
+}
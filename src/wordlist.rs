@@ -1,4 +1,182 @@
-pub const WORDLIST: [&str; 2048] = [
+use crate::WORDLIST_LEN;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+
+/// Errors that can occur when loading a word list.
+#[derive(Debug)]
+pub enum WordlistError {
+    /// The word list file could not be read
+    Io(io::Error),
+    /// A line does not contain a word
+    EmptyEntry { line: usize },
+    /// A word occurs more than once (case-insensitive)
+    Duplicate { word: String, line: usize },
+    /// Less than two words, so no entropy at all
+    TooShort { len: usize },
+}
+
+impl fmt::Display for WordlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "cannot read word list: {}", e),
+            Self::EmptyEntry { line } => write!(f, "empty entry in line {}", line),
+            Self::Duplicate { word, line } => {
+                write!(f, "duplicate word \"{}\" in line {}", word, line)
+            }
+            Self::TooShort { len } => write!(f, "word list has only {} words", len),
+        }
+    }
+}
+
+impl std::error::Error for WordlistError {}
+
+/// Name of the compiled-in default word list.
+pub const DEFAULT_WORDLIST_NAME: &str = "default";
+
+/// Additional compiled-in word lists, selectable by name.
+///
+/// The BIP39 lists are taken from the bip39 crate (CC0) and normalized to NFC,
+/// so that their words can be typed on a keyboard. The EFF lists are published
+/// by the Electronic Frontier Foundation under CC BY 3.0 US, the German list is
+/// the one of the Monero Project (BSD-3-Clause); see `wordlists/NOTICE`.
+const BUILTIN_WORDLISTS: [(&str, &str); 9] = [
+    ("bip39-english", include_str!("wordlists/bip39-english.txt")),
+    ("bip39-french", include_str!("wordlists/bip39-french.txt")),
+    ("bip39-spanish", include_str!("wordlists/bip39-spanish.txt")),
+    ("bip39-italian", include_str!("wordlists/bip39-italian.txt")),
+    (
+        "bip39-portuguese",
+        include_str!("wordlists/bip39-portuguese.txt"),
+    ),
+    ("bip39-czech", include_str!("wordlists/bip39-czech.txt")),
+    ("eff-large", include_str!("wordlists/eff-large.txt")),
+    ("eff-short", include_str!("wordlists/eff-short.txt")),
+    ("monero-german", include_str!("wordlists/monero-german.txt")),
+];
+
+/// A list of distinct words that passwords are built from.
+#[derive(Clone, Debug)]
+pub struct Wordlist {
+    name: String,
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// The compiled-in word list.
+    pub fn builtin() -> Self {
+        // assert word list length so that we don't forget to change this code when
+        // word list length changes.
+        assert!(WORDLIST.len() == WORDLIST_LEN);
+        Self {
+            name: DEFAULT_WORDLIST_NAME.to_string(),
+            words: WORDLIST.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    /// The compiled-in word list with the given name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        if name == DEFAULT_WORDLIST_NAME {
+            return Some(Self::builtin());
+        }
+        let (name, content) = BUILTIN_WORDLISTS.iter().find(|(n, _)| *n == name)?;
+        let mut wordlist = Self::parse(content).expect("Compiled-in word lists are valid");
+        wordlist.name = name.to_string();
+        Some(wordlist)
+    }
+
+    /// Names of all compiled-in word lists.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        std::iter::once(DEFAULT_WORDLIST_NAME).chain(BUILTIN_WORDLISTS.iter().map(|(n, _)| *n))
+    }
+
+    /// Parse a newline-separated word list, rejecting empty entries and duplicates.
+    ///
+    /// The list is named "custom".
+    pub fn parse(content: &str) -> Result<Self, WordlistError> {
+        let mut words = Vec::new();
+        let mut seen = HashSet::new();
+        for (i, line) in content.lines().enumerate() {
+            let word = line.trim();
+            if word.is_empty() {
+                return Err(WordlistError::EmptyEntry { line: i + 1 });
+            }
+            // words are capitalized, so differently cased words would yield the same password
+            if !seen.insert(word.to_lowercase()) {
+                return Err(WordlistError::Duplicate {
+                    word: word.to_string(),
+                    line: i + 1,
+                });
+            }
+            words.push(word.to_string());
+        }
+        if words.len() < 2 {
+            return Err(WordlistError::TooShort { len: words.len() });
+        }
+        Ok(Self {
+            name: "custom".to_string(),
+            words,
+        })
+    }
+
+    /// Load a newline-separated word list from a file, named after its path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WordlistError> {
+        let content = read_to_string(&path).map_err(WordlistError::Io)?;
+        let mut wordlist = Self::parse(&content)?;
+        wordlist.name = path.as_ref().display().to_string();
+        Ok(wordlist)
+    }
+
+    /// Name of the word list.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Short hash over all words, to compare word lists across machines.
+    pub fn fingerprint(&self) -> String {
+        let digest = sha256::digest(self.words.join("\n"));
+        digest[..16].to_string()
+    }
+
+    /// Number of words in the list.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether the list has no words. Never true for a validated list.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Word at the given index.
+    pub fn get(&self, index: usize) -> &str {
+        &self.words[index]
+    }
+
+    /// Number of characters of the longest word.
+    pub fn max_word_chars(&self) -> usize {
+        self.words
+            .iter()
+            .map(|w| w.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Entropy of a single word in bits.
+    pub fn bits_per_word(&self) -> f32 {
+        (self.len() as f32).log2()
+    }
+}
+
+impl Default for Wordlist {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+pub static WORDLIST: [&str; 2048] = [
     "A",
     "ability",
     "able",
@@ -2047,4 +2225,4 @@ pub const WORDLIST: [&str; 2048] = [
     "young",
     "youth",
     "zone",
-    ];
+];
@@ -30,6 +30,7 @@ function assertEquals {
 # make temporary directory for revocation list
 revlistdir="$(mktemp -d)"
 export HOME="$revlistdir"
+export XDG_CONFIG_HOME="$revlistdir/config"
 
 # initialize empty revocation list
 revlist="$revlistdir/$REVLIST_NAME"
@@ -61,18 +62,399 @@ xkcdget_output=$(echo -en "$domain\n$password"|"$BIN")
 assertEquals "$expected" "$xkcdget_output"
 
 
+echo "Acceptance test 1.3: Number of words"
+expected="MindDisappointedDoctorAssureSecondMarry_1"
+call_xkcdget '--words 6'
+assertEquals "$expected" "$xkcdget_output"
+
+echo "Acceptance test 1.4: Custom word list"
+wordlist="$revlistdir/wordlist"
+printf 'alpha\nbeta\ngamma\n' > "$wordlist"
+expected="AlphaBetaGammaBeta_1"
+call_xkcdget "--wordlist $wordlist"
+assertEquals "$expected" "$xkcdget_output"
+
+echo "Acceptance test 1.5: Compiled-in word list"
+expected="LégumeDélogerDésertAnxieux_1"
+call_xkcdget '--list bip39-french'
+assertEquals "$expected" "$xkcdget_output"
+expected="FlailPayphoneVenueSuction_1"
+call_xkcdget '--list eff-large'
+assertEquals "$expected" "$xkcdget_output"
+expected="AgedMashSlimyArgue_1"
+call_xkcdget '--list eff-short'
+assertEquals "$expected" "$xkcdget_output"
+expected="AmulettAdresseFelgeUrmensch_1"
+call_xkcdget '--list monero-german'
+assertEquals "$expected" "$xkcdget_output"
+
+echo "Acceptance test 1.6: Password policy"
+expected="speed.broad.vast!12"
+call_xkcdget "--max-length 20 --symbols '!@' --digits 2 --separator . --case lower"
+assertEquals "$expected" "$xkcdget_output"
+expected="Entropy: 14.3 bits (2 words)"
+actual=$(echo -n "$password" | "$BIN" --max-length 8 "$domain" 2>&1 >/dev/null | grep '^Entropy:')
+assertEquals "$expected" "$actual"
+
+echo "Acceptance test 1.7: Domain normalization"
+domain='HTTPS://Www.Example.Net:443/login'
+expected="BonusTreePeakRegular_1"
+call_xkcdget
+assertEquals "$expected" "$xkcdget_output"
+
+for pair in 'bücher.de xn--bcher-kva.de' 'münchen.de xn--mnchen-3ya.de' '例え.jp xn--r8jz45g.jp' '2001:db8::1 2001:db8::1' '[::1]:8080 [::1]'; do
+    read -r input expected <<< "$pair"
+    actual=$(echo -n "$password" | "$BIN" get --format json "$input" | grep -o '"canonical_domain":"[^"]*"' | cut -d'"' -f4)
+    assertEquals "$expected" "$actual"
+done
+
+echo "Acceptance test 1.8: Raw domain"
+domain='Example.net'
+expected="StyleAddPoetryLonely_1"
+call_xkcdget '--raw-domain'
+assertEquals "$expected" "$xkcdget_output"
+domain='domain'
+
+echo "Acceptance test 1.9: User name"
+expected="CrossStatementSleepSurprise_1"
+call_xkcdget '--user alice'
+assertEquals "$expected" "$xkcdget_output"
+
+echo "Acceptance test 1.10: Specific iteration"
+expected="FunnyFewStrikeContent_1"
+call_xkcdget '--iteration 1'
+assertEquals "$expected" "$xkcdget_output"
+
+
 echo "Acceptance test 2: Revocation"
 
 echo
 echo "Acceptance test 2.1: Shorthand flag"
 call_xkcdget '-r'
 expected=':&a*5wnoz{0tUw#9U}+!s7qdGlqGo9XhHURZz>r1'
-actual=$(tail -1 "$revlist")
+actual=$(tail -1 "$revlist" | cut -d" " -f1)
 assertEquals "$expected" "$actual"
 
 echo
 echo "Acceptance test 2.2: Longhand flag, double revocation"
 call_xkcdget '--revoke'
 expected='([Z>a9^-KV)T&]R(MH41ykWS>JxWBKIu^Nyhxg{)'
+actual=$(tail -1 "$revlist" | cut -d" " -f1)
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 2.3: Reason and label"
+call_xkcdget '--revoke --reason compromised --label work'
+expected='compromised work'
+actual=$(echo -n "$password" | "$BIN" revocations list --labels | tail -1 | awk '{print $3, $5}')
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 2.4: Undo revocation"
+call_xkcdget 'unrevoke'
+expected='([Z>a9^-KV)T&]R(MH41ykWS>JxWBKIu^Nyhxg{)'
+actual=$(tail -1 "$revlist" | cut -d" " -f1)
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 2.5: History"
+call_xkcdget 'history --passwords'
+expected='2 active LeaveAccountSayDig_1'
+actual=$(echo "$xkcdget_output" | tail -1 | awk '{print $1, $2, $3}')
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 2.6: Dry run shows old and new password"
+revlist_before=$(cat "$revlist")
+call_xkcdget '--iteration 3'
+next_password="$xkcdget_output"
+call_xkcdget '--revoke --dry-run --passwords'
+assertEquals "$next_password" "$xkcdget_output"
+actual=$(cat "$revlist")
+assertEquals "$revlist_before" "$actual"
+
+echo
+echo "Acceptance test 2.7: Concurrent revocations"
+lines_before=$(wc -l < "$revlist")
+for concurrent_domain in one.example two.example three.example four.example; do
+    echo -n "$password" | "$BIN" --revoke "$concurrent_domain" >/dev/null 2>&1 &
+done
+wait
+expected=$((lines_before + 4))
+actual=$(wc -l < "$revlist")
+assertEquals "$expected" "$actual"
+"$BIN" revocations list >/dev/null
+
+echo
+echo "Acceptance test 2.8: Diff and merge revocation files"
+other_revlist="$revlistdir/other-revocation"
+first_hash=$(sed -n 2p "$revlist" | cut -d" " -f1)
+other_hash='0000000000000000000000000000000000000000'
+cat > "$other_revlist" <<EOF
+xkcdget-revocation 2
+$first_hash 4102444800 compromised
+$other_hash 4102444800 rotation
+EOF
+expected="> $other_hash 4102444800 rotation"
+actual=$("$BIN" revocations diff "$other_revlist" | grep '^>' || true)
+assertEquals "$expected" "$actual"
+"$BIN" revocations merge "$other_revlist"
+expected="$first_hash 4102444800 compromised"
+actual=$(sed -n 2p "$revlist")
+assertEquals "$expected" "$actual"
+expected="$other_hash 4102444800 rotation"
 actual=$(tail -1 "$revlist")
 assertEquals "$expected" "$actual"
+"$BIN" revocations merge "$other_revlist" "$revlist"
+expected=$(grep -c . "$revlist")
+actual=$(sort -u "$revlist" | grep -c .)
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 2.9: Revocation file location and read-only sources"
+personal_revlist="$revlistdir/data/personal"
+XKCDGET_REVOCATION_FILE="$personal_revlist" call_xkcdget '--revoke'
+actual=$(grep -c . "$personal_revlist")
+assertEquals "2" "$actual"
+call_xkcdget "--revocation-file $personal_revlist"
+personal_password="$xkcdget_output"
+call_xkcdget "--revocation-source $personal_revlist --revocation-file $revlistdir/data/empty"
+assertEquals "$personal_password" "$xkcdget_output"
+if echo -n "$password" | "$BIN" unrevoke --revocation-source "$personal_revlist" --revocation-file "$revlistdir/data/empty" "$domain" 2>/dev/null; then
+    (>&2 echo "Unrevoking from a read-only revocation file should fail.")
+    exit 1
+fi
+cp "$personal_revlist" "$revlistdir/data/personal-copy"
+if echo -n "$password" | "$BIN" unrevoke --revocation-source "$personal_revlist" --revocation-file "$revlistdir/data/personal-copy" "$domain" 2>/dev/null; then
+    (>&2 echo "Unrevoking a hash that a read-only revocation file revokes too should fail.")
+    exit 1
+fi
+assertEquals "$(cat "$personal_revlist")" "$(cat "$revlistdir/data/personal-copy")"
+xdg_home="$revlistdir/xdg-home"
+mkdir -p "$xdg_home"
+HOME="$xdg_home" call_xkcdget '--revoke'
+touch "$xdg_home/$REVLIST_NAME"
+HOME="$xdg_home" call_xkcdget
+assertEquals "$personal_password" "$xkcdget_output"
+
+echo
+echo "Acceptance test 2.10: Authenticated revocation file"
+auth_revlist="$revlistdir/data/authenticated"
+export XKCDGET_REVOCATION_FILE="$auth_revlist"
+call_xkcdget '--revoke'
+echo -n "$password" | "$BIN" revocations authenticate
+call_xkcdget '--revoke'
+expected='xkcdget-revocation 3'
+actual=$(head -1 "$auth_revlist")
+assertEquals "$expected" "$actual"
+cp "$auth_revlist" "$auth_revlist.orig"
+sed -i 2d "$auth_revlist"
+if echo -n "$password" | "$BIN" "$domain" 2>/dev/null; then
+    (>&2 echo "Reading a modified authenticated revocation file should fail.")
+    exit 1
+fi
+{ echo 'xkcdget-revocation 2'; grep -v -e '^mac' -e '^xkcdget-revocation' "$auth_revlist.orig"; } > "$auth_revlist"
+if echo -n "$password" | "$BIN" "$domain" 2>/dev/null; then
+    (>&2 echo "Reading an authenticated revocation file that lost its MACs should fail.")
+    exit 1
+fi
+cp "$auth_revlist.orig" "$auth_revlist"
+echo -n "$password" | "$BIN" --revoke first.example >/dev/null
+echo -n "$password" | "$BIN" --revoke second.example >/dev/null
+actual=$(grep -c '^mac' "$auth_revlist")
+assertEquals 1 "$actual"
+state="$HOME/.local/state/xkcdget/revocation-state"
+cp "$state" "$state.orig"
+head -n -2 "$auth_revlist" > "$auth_revlist.truncated"
+mv "$auth_revlist.truncated" "$auth_revlist"
+rm "$state"
+if echo -n "$password" | "$BIN" "$domain" 2>/dev/null; then
+    (>&2 echo "Reading an authenticated revocation file that lost its latest entries should fail.")
+    exit 1
+fi
+mv "$state.orig" "$state"
+unset XKCDGET_REVOCATION_FILE
+
+echo
+echo "Acceptance test 2.11: Missing revocation file"
+mv "$revlist" "$revlist.moved"
+if echo -n "$password" | "$BIN" "$domain" 2>/dev/null; then
+    (>&2 echo "Reading a revocation file that went missing should fail.")
+    exit 1
+fi
+call_xkcdget '--accept-revocation-loss'
+mv "$revlist.moved" "$revlist"
+call_xkcdget ''
+
+echo
+echo "Acceptance test 2.12: Export and import"
+transfer=$("$BIN" revocations export)
+"$BIN" revocations export --qr >/dev/null
+XKCDGET_REVOCATION_FILE="$revlistdir/data/imported" "$BIN" revocations import "$transfer"
+actual=$(XKCDGET_REVOCATION_FILE="$revlistdir/data/imported" "$BIN" revocations export)
+assertEquals "$transfer" "$actual"
+if "$BIN" revocations import "${transfer%?????}" 2>/dev/null; then
+    (>&2 echo "Importing a truncated string should fail.")
+    exit 1
+fi
+many_revlist="$revlistdir/data/many"
+{ echo 'xkcdget-revocation 2'; for i in $(seq 45); do printf '%040d 4102444800 -\n' "$i"; done; } > "$many_revlist"
+transfer=$(XKCDGET_REVOCATION_FILE="$many_revlist" "$BIN" revocations export 2>/dev/null)
+actual=$(echo "$transfer" | wc -l)
+assertEquals 3 "$actual"
+XKCDGET_REVOCATION_FILE="$many_revlist" "$BIN" revocations export --qr >/dev/null 2>&1
+echo "$transfer" | tac | XKCDGET_REVOCATION_FILE="$revlistdir/data/many-imported" "$BIN" revocations import
+actual=$(XKCDGET_REVOCATION_FILE="$revlistdir/data/many-imported" "$BIN" revocations export 2>/dev/null)
+assertEquals "$transfer" "$actual"
+if echo "$transfer" | head -2 | "$BIN" revocations import 2>/dev/null; then
+    (>&2 echo "Importing an incomplete export should fail.")
+    exit 1
+fi
+
+echo
+echo "Acceptance test 2.13: Malformed revocation file"
+malformed_revlist="$revlistdir/data/malformed"
+printf 'xkcdget-revocation 2\n%s 4102444800 -\nnot a hash\n' "$other_hash" > "$malformed_revlist"
+expected='line 3'
+actual=$(XKCDGET_REVOCATION_FILE="$malformed_revlist" "$BIN" revocations list 2>&1 | grep -o 'line 3' || true)
+assertEquals "$expected" "$actual"
+
+
+echo "Acceptance test 3: Configuration"
+
+config="$XDG_CONFIG_HOME/xkcdget/sites.toml"
+mkdir -p "$(dirname "$config")"
+cat > "$config" <<EOF
+[default]
+words = 4
+
+[sites."example.com"]
+max_length = 20 # short passwords only
+separator = "."
+case = "lower"
+
+[sites."example.org"]
+alias = "example.com"
+EOF
+
+echo
+echo "Acceptance test 3.1: Check configuration"
+"$BIN" config check
+
+echo
+echo "Acceptance test 3.2: Site profile and alias"
+domain='www.example.org'
+expected="real.bear.local_1"
+call_xkcdget
+assertEquals "$expected" "$xkcdget_output"
+domain='domain'
+
+echo
+echo "Acceptance test 3.3: No configuration directory"
+expected="ConvertSpellPossessConference_1"
+actual=$(echo -n "$password" | env -u HOME -u XDG_CONFIG_HOME XKCDGET_REVOCATION_FILE="$revlistdir/data/no-config" "$BIN" example.com)
+assertEquals "$expected" "$actual"
+
+echo
+echo "Acceptance test 3.4: Check configuration without HOME"
+env -u HOME "$BIN" config check
+
+echo
+echo "Acceptance test 3.5: Table names and aliases that aren't canonical"
+noncanonical_config_home="$revlistdir/noncanonical-config"
+mkdir -p "$noncanonical_config_home/xkcdget"
+cat > "$noncanonical_config_home/xkcdget/sites.toml" <<EOF
+[sites."WWW.Example.com"]
+max_length = 20
+separator = "."
+case = "lower"
+
+[sites."example.org"]
+alias = "https://www.Example.com/login"
+EOF
+expected="real.bear.local_1"
+actual=$(echo -n "$password" | XDG_CONFIG_HOME="$noncanonical_config_home" "$BIN" example.org)
+assertEquals "$expected" "$actual"
+status=0
+XDG_CONFIG_HOME="$noncanonical_config_home" "$BIN" config check >/dev/null 2>&1 || status=$?
+assertEquals 1 "$status"
+
+
+echo
+echo "Acceptance test 4: Command line"
+
+echo
+echo "Acceptance test 4.1: Help"
+expected='Usage: xkcdget [COMMAND] [OPTIONS] [--] [DOMAIN]'
+actual=$("$BIN" --help | head -n 1)
+assertEquals "$expected" "$actual"
+
+echo "Acceptance test 4.2: Unknown option"
+status=0
+echo -n "$password" | "$BIN" get --unknown "$domain" >/dev/null 2>&1 || status=$?
+assertEquals 2 "$status"
+
+echo "Acceptance test 4.3: Explicit get command"
+call_xkcdget
+expected="$xkcdget_output"
+call_xkcdget 'get'
+assertEquals "$expected" "$xkcdget_output"
+
+echo "Acceptance test 4.4: Domain beginning with a dash"
+expected=$(echo -n "$password" | "$BIN" --raw-domain -- -domain 2>/dev/null)
+actual=$(echo -n "$password" | "$BIN" get --raw-domain -- -domain 2>/dev/null)
+assertEquals "$expected" "$actual"
+
+echo "Acceptance test 4.5: Option of another command"
+status=0
+echo -n "$password" | "$BIN" get --dry-run "$domain" >/dev/null 2>&1 || status=$?
+assertEquals 2 "$status"
+
+echo "Acceptance test 4.6: Exit status of errors"
+status=0
+"$BIN" "$domain" </dev/null >/dev/null 2>&1 || status=$?
+assertEquals 3 "$status"
+status=0
+XKCDGET_REVOCATION_FILE="$malformed_revlist" "$BIN" revocations list >/dev/null 2>&1 || status=$?
+assertEquals 6 "$status"
+status=0
+echo -n "$password" | "$BIN" unrevoke never-revoked.example >/dev/null 2>&1 || status=$?
+assertEquals 9 "$status"
+status=0
+echo -n "$password" | "$BIN" 'https://' >/dev/null 2>&1 || status=$?
+assertEquals 3 "$status"
+
+echo "Acceptance test 4.7: Quiet and verbose"
+expected=''
+actual=$(echo -n "$password" | "$BIN" --quiet "$domain" 2>&1 >/dev/null)
+assertEquals "$expected" "$actual"
+expected="Revocation file: $revlist"
+actual=$(echo -n "$password" | "$BIN" -v "$domain" 2>&1 >/dev/null | grep '^Revocation file:')
+assertEquals "$expected" "$actual"
+expected=''
+actual=$(echo -n "$password" | "$BIN" get --separator -v "$domain" 2>&1 >/dev/null | grep '^Revocation file:' || true)
+assertEquals "$expected" "$actual"
+expected='Would revoke'
+actual=$(echo -n "$password" | "$BIN" revoke --dry-run --label -h "$domain" 2>&1 | grep -o 'Would revoke' || true)
+assertEquals "$expected" "$actual"
+
+echo "Acceptance test 4.8: JSON and env output"
+format_password=$(echo -n "$password" | "$BIN" get --raw-domain format.example 2>/dev/null)
+actual=$(echo -n "$password" | "$BIN" get --raw-domain --format json format.example 2>/dev/null | grep -o '"password":"[^"]*"')
+assertEquals "\"password\":\"$format_password\"" "$actual"
+actual=$(eval "$(echo -n "$password" | "$BIN" get --raw-domain --format env format.example 2>/dev/null)"; echo "$XKCDGET_CANONICAL_DOMAIN $XKCDGET_ITERATION $XKCDGET_PASSWORD")
+assertEquals "format.example 0 $format_password" "$actual"
+actual=$(echo -n "$password" | "$BIN" get --raw-domain --max-length 8 --format json format.example 2>/dev/null | grep -o '"entropy_bits":[0-9]*')
+assertEquals '"entropy_bits":14' "$actual"
+
+echo "Acceptance test 4.9: Batch"
+batch_file="$revlistdir/batch"
+printf 'format.example\n\nexample.org\n' > "$batch_file"
+alias_password=$(echo -n "$password" | "$BIN" get example.org 2>/dev/null)
+actual=$(echo -n "$password" | "$BIN" batch "$batch_file" 2>/dev/null)
+assertEquals "$(printf 'format.example\t%s\nexample.org\t%s' "$format_password" "$alias_password")" "$actual"
+printf 'format.example\0example.org' > "$batch_file"
+actual=$(echo -n "$password" | "$BIN" batch --null --format json "$batch_file" 2>/dev/null | grep -c '"password":')
+assertEquals 2 "$actual"
+actual=$(echo -n "$password" | "$BIN" batch --from-config 2>/dev/null | cut -f 1)
+assertEquals "example.com" "$actual"